@@ -2,7 +2,7 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use webgl2_glyph_atlas::{Font, Renderer};
+use webgl2_glyph_atlas::{Font, GlyphMode, Renderer, RendererOptions, TextHandle, TextStyle};
 use web_sys::WebGl2RenderingContext;
 
 #[allow(unused)]
@@ -16,17 +16,35 @@ macro_rules! console_log {
 struct RenderContext {
     _gl: &'static WebGl2RenderingContext,
     renderer: Renderer<'static>,
+    text: TextHandle,
     frame: u32,
 }
 
 impl RenderContext {
     pub fn new(gl: WebGl2RenderingContext) -> RenderContext {
         let gl = Box::leak(Box::new(gl));
-        let renderer = Renderer::new(gl).unwrap();
-        
+        // The drawing buffer is the canvas's CSS size, so draw a device pixel per
+        // pixel (see hello-world for sharp text on high-DPI displays).
+        let options = RendererOptions::new()
+            .pixel_ratio(1.)
+            .glyph_mode(GlyphMode::SignedDistanceField {
+                size: 48,
+                spread: 6,
+            });
+        let mut renderer = Renderer::with_options(gl, &options).unwrap();
+        // The text is laid out once, and again only when its size changes.
+        let text = renderer.add_text(
+            "Hello world ",
+            &Font::new("Georgia", 20.),
+            40.,
+            1.,
+            &TextStyle::default(),
+        );
+
         RenderContext {
             _gl: gl,
             renderer,
+            text,
             frame: 0,
         }
     }
@@ -35,11 +53,14 @@ impl RenderContext {
         let start_time = web_sys::window().unwrap().performance().unwrap().now();
 
         let f = ((self.frame as f32) % 200.) + 1.;
+        // Glyphs are stored as distance fields, so zooming doesn't rasterize anything new.
+        let size = 20. + (self.frame / 4 % 60) as f32;
 
         //self.gl.clear_color(1.0, 1.0, 1.0, 1.0);
         //self.gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
 
-        self.renderer.queue_text("Hello world ", &Font::new("Georgia", 40), 40., f);
+        self.renderer.set_text_font(self.text, &Font::new("Georgia", size));
+        self.renderer.set_text_position(self.text, 40., f);
         self.renderer.draw().unwrap();
         self.frame += 1;
 
@@ -1,19 +1,20 @@
 use wasm_bindgen::JsCast;
-use web_sys::{WebGl2RenderingContext, FontFace};
+use web_sys::WebGl2RenderingContext;
 
-use webgl2_glyph_atlas::{Font, Renderer};
+use webgl2_glyph_atlas::{Font, FontRegistry, Renderer, RendererOptions};
 
-async fn load_font(family: &str, source: &str) {
-    let font_promise = FontFace::new_with_str(family, source).unwrap();
-    let result = wasm_bindgen_futures::JsFuture::from(font_promise.load().unwrap()).await.unwrap();
-    let font = result.dyn_into::<FontFace>().unwrap();
+fn draw(gl: &WebGl2RenderingContext, renderer: &mut Renderer) {
+    gl.clear_color(1.0, 1.0, 1.0, 1.0);
+    gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
 
-    let document = web_sys::window().unwrap().document().unwrap();
-    document.fonts().add(&font).unwrap();
+    renderer.queue_text("Hello world ", &Font::new("Pacifico", 40.), 10., 120.);
+
+    renderer.draw().unwrap();
 }
 
 async fn draw_text() {
-    load_font("Pacifico", "url('Pacifico-Regular.ttf')").await;
+    let fonts = FontRegistry::new();
+    fonts.load_url("Pacifico", "Pacifico-Regular.ttf").unwrap();
 
     let document = web_sys::window().unwrap().document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
@@ -24,15 +25,17 @@ async fn draw_text() {
         .unwrap()
         .dyn_into::<WebGl2RenderingContext>().unwrap();
 
-    let mut renderer = Renderer::new(&gl).unwrap();
+    // The drawing buffer is the canvas's CSS size, so draw a device pixel per
+    // pixel (see hello-world for sharp text on high-DPI displays).
+    let options = RendererOptions::new().pixel_ratio(1.);
+    let mut renderer = Renderer::with_options(&gl, &options).unwrap();
+    renderer.use_font_registry(&fonts);
 
-    gl.blend_func(WebGl2RenderingContext::ONE, WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA);
-    gl.clear_color(1.0, 1.0, 1.0, 1.0);
-    gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    // Drawn with a fallback font until Pacifico has loaded.
+    draw(&gl, &mut renderer);
 
-    renderer.queue_text("Hello world ", &Font::new("Pacifico", 40), 10., 120.);
-
-    renderer.draw().unwrap();
+    fonts.ready().await.unwrap();
+    draw(&gl, &mut renderer);
 }
 
 pub fn main() {
@@ -2,26 +2,55 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::WebGl2RenderingContext;
 
-use webgl2_glyph_atlas::{Font, Renderer};
+use webgl2_glyph_atlas::{Font, Renderer, RendererOptions, TextAlign, TextStyle, YAxis};
 
 pub fn main() -> Result<(), JsValue> {
     let document = web_sys::window().unwrap().document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
     let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
 
+    // Give the canvas a drawing buffer in device pixels, keeping its size on the
+    // page, so that text is sharp on high-DPI displays.
+    let pixel_ratio = web_sys::window().unwrap().device_pixel_ratio();
+    let (width, height) = (canvas.client_width(), canvas.client_height());
+    canvas.set_attribute("style", &format!("width: {}px; height: {}px;", width, height))?;
+    canvas.set_width((width as f64 * pixel_ratio) as u32);
+    canvas.set_height((height as f64 * pixel_ratio) as u32);
+
     let gl = canvas
         .get_context("webgl2")?
         .unwrap()
         .dyn_into::<WebGl2RenderingContext>()?;
 
-    let mut renderer = Renderer::new(&gl).unwrap();
+    // Position text from the top left of the canvas, as in the DOM.
+    let options = RendererOptions::new().y_axis(YAxis::Down);
+    let mut renderer = Renderer::with_options(&gl, &options).unwrap();
 
     gl.clear_color(1.0, 1.0, 1.0, 1.0);
     gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
 
-    renderer.queue_text("Hello world ", &Font::new("Georgia", 10), 40., 120.);
-    renderer.queue_text("Hello, world! 🌎", &Font::new("Arial", 30), 10., 200.);
-    renderer.queue_text("こんにちは世界", &Font::new("Helvetica", 25), 10., 10.);
+    renderer.queue_text("Hello world ", &Font::new("Georgia", 10.), 40., 130.);
+    renderer.queue_text_with_style(
+        "Hello, world! 🌎",
+        &Font::new("Arial", 30.),
+        10.,
+        50.,
+        &TextStyle::new().color([0.1, 0.3, 0.8, 1.]),
+    );
+    renderer.queue_text_with_style(
+        "こんにちは世界",
+        &Font::new("Helvetica", 25.),
+        10.,
+        240.,
+        &TextStyle::new().color([0.8, 0.1, 0.1, 0.7]),
+    );
+    renderer.queue_text_with_style(
+        "Text is wrapped to fit within a maximum width,\nand lines can be aligned.",
+        &Font::new("Georgia", 16.),
+        10.,
+        170.,
+        &TextStyle::new().max_width(200.).align(TextAlign::Center),
+    );
 
     renderer.draw().unwrap();
 
@@ -2,7 +2,7 @@ use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::WebGl2RenderingContext;
 
-use webgl2_glyph_atlas::{log_error, Font, Renderer};
+use webgl2_glyph_atlas::{log_error, Font, Renderer, RendererOptions};
 
 pub fn main() -> Result<(), JsValue> {
     let document = web_sys::window().unwrap().document().unwrap();
@@ -14,23 +14,22 @@ pub fn main() -> Result<(), JsValue> {
         .unwrap()
         .dyn_into::<WebGl2RenderingContext>()?;
 
-    let mut renderer = Renderer::new(&gl).unwrap();
-    gl.blend_func(
-        WebGl2RenderingContext::ONE,
-        WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
-    );
+    // The drawing buffer is the canvas's CSS size, so draw a device pixel per
+    // pixel (see hello-world for sharp text on high-DPI displays).
+    let options = RendererOptions::new().pixel_ratio(1.);
+    let mut renderer = Renderer::with_options(&gl, &options).unwrap();
 
     gl.clear_color(1.0, 1.0, 1.0, 1.0);
     gl.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
 
-    renderer.queue_text("Hello world", &Font::new("Georgia", 40), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 45), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 50), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 55), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 60), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 65), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 70), 40., 200.);
-    renderer.queue_text("Hello world", &Font::new("Georgia", 75), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 40.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 45.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 50.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 55.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 60.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 65.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 70.), 40., 200.);
+    renderer.queue_text("Hello world", &Font::new("Georgia", 75.), 40., 200.);
 
     let _ = renderer.draw().map_err(log_error);
 
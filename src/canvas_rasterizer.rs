@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use unicode_segmentation::UnicodeSegmentation;
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, TextMetrics};
+
+use crate::dom::get_document;
+use crate::error::{GlyphAtlasError, Result};
+use crate::glyph_atlas::FontIndex;
+use crate::rasterizer::{
+    FontMetrics, GlyphBitmap, GlyphId, GlyphRasterizer, GlyphShape, ShapedGlyph,
+};
+use crate::Font;
+
+/// Rasterizes glyphs by drawing them to a hidden canvas element, so that any
+/// font available to the page can be used.
+///
+/// The canvas API doesn't expose glyphs, so each extended grapheme cluster (a
+/// character along with any combining marks, or an emoji sequence like a flag)
+/// is drawn as one glyph. Single characters are their own glyph ID, and longer
+/// clusters are given one the first time they are seen; past
+/// `MAX_CLUSTERS` of those, new ones are drawn a character at a time. Kerning
+/// is recovered by measuring pairs of clusters, but ligatures between clusters
+/// and the joining of complex scripts are lost.
+///
+/// The canvas doesn't say which family a cluster was drawn with either. For
+/// fonts with several families, a family is taken to have a cluster if adding
+/// it in front of a generic family changes the cluster's width.
+pub struct CanvasRasterizer {
+    canvas: HtmlCanvasElement,
+    canvas_context: CanvasRenderingContext2d,
+    width: u32,
+    height: u32,
+    /// Text of each cluster of more than one character, indexed by glyph ID
+    /// minus `FIRST_CLUSTER_ID`.
+    clusters: Vec<String>,
+    cluster_ids: HashMap<String, GlyphId>,
+    /// Fonts measurements were taken in, so that they can be keyed on an index.
+    fonts: Vec<Font>,
+    font_to_index: HashMap<Font, FontIndex>,
+    /// Measured widths of single clusters and pairs of clusters.
+    text_widths: HashMap<(FontIndex, String), f32>,
+    /// Whether the family of a single-family font has a cluster.
+    has_cluster: HashMap<(FontIndex, String), bool>,
+}
+
+/// Glyph ID of the first cluster of more than one character. Lower IDs are the
+/// code points of single characters.
+const FIRST_CLUSTER_ID: GlyphId = 0x11_0000;
+
+/// Number of clusters of more than one character that are given IDs. IDs are
+/// never reused, since glyphs may be kept in the atlas for as long as it likes.
+const MAX_CLUSTERS: usize = 65536;
+
+/// Number of cached measurements past which they are all forgotten.
+const MAX_MEASUREMENTS: usize = 16384;
+
+impl CanvasRasterizer {
+    pub fn new() -> Result<CanvasRasterizer> {
+        let document = get_document()?;
+
+        let canvas = document
+            .create_element("canvas")
+            .map_err(|_| {
+                GlyphAtlasError::DomError("Could not construct canvas element.".to_string())
+            })?
+            .dyn_into::<HtmlCanvasElement>()
+            .map_err(|_| GlyphAtlasError::DomError("Could not cast canvas element.".to_string()))?;
+
+        let canvas_context = canvas
+            .get_context("2d")
+            .map_err(|_| GlyphAtlasError::DomError("Could not get context.".to_string()))?
+            .ok_or_else(|| {
+                GlyphAtlasError::DomError(
+                    "A non-2D context has already been requested from this canvas.".to_string(),
+                )
+            })?
+            .dyn_into::<CanvasRenderingContext2d>()
+            .map_err(|_| GlyphAtlasError::DomError("Could not cast canvas context.".to_string()))?;
+
+        Ok(CanvasRasterizer {
+            canvas,
+            canvas_context,
+            width: 0,
+            height: 0,
+            clusters: Vec::new(),
+            cluster_ids: HashMap::new(),
+            fonts: Vec::new(),
+            font_to_index: HashMap::new(),
+            text_widths: HashMap::new(),
+            has_cluster: HashMap::new(),
+        })
+    }
+
+    /// The glyph ID of a cluster, or `None` if it has more than one character
+    /// and there are no IDs left.
+    fn cluster_id(&mut self, cluster: &str) -> Option<GlyphId> {
+        let mut chars = cluster.chars();
+        if let (Some(ch), None) = (chars.next(), chars.next()) {
+            return Some(ch as GlyphId);
+        }
+
+        if let Some(id) = self.cluster_ids.get(cluster) {
+            return Some(*id);
+        }
+        if self.clusters.len() >= MAX_CLUSTERS {
+            return None;
+        }
+
+        let id = FIRST_CLUSTER_ID + self.clusters.len() as GlyphId;
+        self.clusters.push(cluster.to_string());
+        self.cluster_ids.insert(cluster.to_string(), id);
+
+        Some(id)
+    }
+
+    /// The text to draw for a glyph ID produced by `shape_text`.
+    fn glyph_text(&self, glyph: GlyphId) -> Result<String> {
+        let text = if glyph < FIRST_CLUSTER_ID {
+            std::char::from_u32(glyph).map(String::from)
+        } else {
+            self.clusters
+                .get((glyph - FIRST_CLUSTER_ID) as usize)
+                .cloned()
+        };
+
+        text.ok_or_else(|| GlyphAtlasError::InternalError("Invalid glyph ID.".to_string()))
+    }
+
+    fn font_to_index(&mut self, font: &Font) -> FontIndex {
+        if let Some(index) = self.font_to_index.get(font) {
+            *index
+        } else {
+            let index = self.fonts.len();
+            self.fonts.push(font.clone());
+            self.font_to_index.insert(font.clone(), index);
+
+            index
+        }
+    }
+
+    /// Forgets every measurement once there are too many, so that the caches
+    /// don't keep growing with every size and string drawn.
+    fn limit_measurements(&mut self) {
+        if self.text_widths.len() + self.has_cluster.len() > MAX_MEASUREMENTS {
+            self.text_widths.clear();
+            self.has_cluster.clear();
+            self.fonts.clear();
+            self.font_to_index.clear();
+        }
+    }
+
+    /// Width of a short string in the given font. Changes the font of the
+    /// context.
+    fn text_width(&mut self, text: String, font: &Font) -> Result<f32> {
+        let key = (self.font_to_index(font), text);
+        if let Some(width) = self.text_widths.get(&key) {
+            return Ok(*width);
+        }
+
+        self.canvas_context.set_font(&font.as_canvas_string());
+        let metrics = self
+            .canvas_context
+            .measure_text(&key.1)
+            .map_err(|_| GlyphAtlasError::DomError("Could not measure text.".to_string()))?;
+        let width = metrics.width() as f32;
+        self.text_widths.insert(key, width);
+
+        Ok(width)
+    }
+
+    /// Whether the family of `face`, a font with a single family, has glyphs for
+    /// a cluster. If it doesn't, the browser draws the cluster with a fallback
+    /// font whatever family is put in front of it, so its width is the same as
+    /// with the fallback alone. Comparing against two generic families makes it
+    /// unlikely for a family that does have the cluster to match both by chance.
+    fn family_has_cluster(&mut self, cluster: &str, face: &Font) -> Result<bool> {
+        let key = (self.font_to_index(face), cluster.to_string());
+        if let Some(has_cluster) = self.has_cluster.get(&key) {
+            return Ok(*has_cluster);
+        }
+
+        let mut has_cluster = false;
+        for generic in &["monospace", "serif"] {
+            let with_family = face.clone().fallback(generic);
+            let without_family = with_family.face(1);
+
+            if self.text_width(cluster.to_string(), &with_family)?
+                != self.text_width(cluster.to_string(), &without_family)?
+            {
+                has_cluster = true;
+                break;
+            }
+        }
+        self.has_cluster.insert(key, has_cluster);
+
+        Ok(has_cluster)
+    }
+
+    /// Index of the first family of the font that has a cluster, or of the first
+    /// family if none do, which leaves the choice of fallback to the browser.
+    fn face_for_cluster(&mut self, cluster: &str, font: &Font) -> Result<usize> {
+        if font.families().len() > 1 {
+            for index in 0..font.families().len() {
+                if self.family_has_cluster(cluster, &font.face(index))? {
+                    return Ok(index);
+                }
+            }
+        }
+
+        Ok(0)
+    }
+
+    /// Makes sure the canvas is at least as large as the given size.
+    fn reserve(&mut self, width: u32, height: u32) -> Result<()> {
+        if width <= self.width && height <= self.height {
+            return Ok(());
+        }
+
+        self.width = self.width.max(width);
+        self.height = self.height.max(height);
+
+        self.canvas
+            .set_attribute("width", &self.width.to_string())
+            .map_err(|_| GlyphAtlasError::DomError("Could not set attribute.".to_string()))?;
+        self.canvas
+            .set_attribute("height", &self.height.to_string())
+            .map_err(|_| GlyphAtlasError::DomError("Could not set attribute.".to_string()))?;
+
+        Ok(())
+    }
+}
+
+impl GlyphRasterizer for CanvasRasterizer {
+    fn shape_text(&mut self, text: &str, font: &Font) -> Result<Vec<ShapedGlyph>> {
+        self.limit_measurements();
+
+        let mut clusters: Vec<(usize, &str, GlyphId)> = Vec::new();
+        for (offset, cluster) in text.grapheme_indices(true) {
+            match self.cluster_id(cluster) {
+                Some(id) => clusters.push((offset, cluster, id)),
+                None => {
+                    for (index, ch) in cluster.char_indices() {
+                        let end = index + ch.len_utf8();
+                        clusters.push((offset + index, &cluster[index..end], ch as GlyphId));
+                    }
+                }
+            }
+        }
+        let mut glyphs = Vec::with_capacity(clusters.len());
+
+        for (i, &(offset, cluster, glyph)) in clusters.iter().enumerate() {
+            // The advance of a cluster, including its kerning with the next one,
+            // is the width of the pair minus the width of the next one.
+            let x_advance = match clusters.get(i + 1) {
+                Some(&(_, next, _)) => {
+                    self.text_width(format!("{}{}", cluster, next), font)?
+                        - self.text_width(next.to_string(), font)?
+                }
+                None => self.text_width(cluster.to_string(), font)?,
+            };
+
+            glyphs.push(ShapedGlyph {
+                glyph,
+                face: self.face_for_cluster(cluster, font)?,
+                cluster: offset,
+                x_offset: 0.,
+                y_offset: 0.,
+                x_advance,
+            });
+        }
+
+        Ok(glyphs)
+    }
+
+    fn font_metrics(&mut self, font: &Font) -> Result<FontMetrics> {
+        self.canvas_context.set_font(&font.as_canvas_string());
+
+        // The font bounding box is the same whatever the text.
+        let metrics = self
+            .canvas_context
+            .measure_text("")
+            .map_err(|_| GlyphAtlasError::DomError("Could not measure text.".to_string()))?;
+
+        Ok(FontMetrics {
+            ascent: metrics.font_bounding_box_ascent() as f32,
+            descent: metrics.font_bounding_box_descent() as f32,
+            // Canvas doesn't expose the line gap.
+            line_gap: 0.,
+        })
+    }
+
+    fn measure_glyph(&mut self, glyph: GlyphId, font: &Font) -> Result<GlyphShape> {
+        self.canvas_context.set_font(&font.as_canvas_string());
+
+        let metrics: TextMetrics = self
+            .canvas_context
+            .measure_text(&self.glyph_text(glyph)?)
+            .map_err(|_| GlyphAtlasError::DomError("Could not measure text.".to_string()))?;
+
+        Ok(glyph_shape_from_text_metrics(&metrics))
+    }
+
+    fn rasterize_glyph(
+        &mut self,
+        glyph: GlyphId,
+        font: &Font,
+        shape: &GlyphShape,
+        offset: [f32; 2],
+    ) -> Result<GlyphBitmap> {
+        let width = shape.glyph_width();
+        let height = shape.height();
+
+        if width == 0 || height == 0 {
+            return Ok(GlyphBitmap::new(width, height));
+        }
+
+        // Resizing the canvas resets the context, so this has to come before
+        // setting the font.
+        self.reserve(width, height)?;
+
+        self.canvas_context
+            .clear_rect(0., 0., width as f64, height as f64);
+        self.canvas_context.set_font(&font.as_canvas_string());
+        self.canvas_context.set_fill_style_str("white");
+        self.canvas_context
+            .fill_text(
+                &self.glyph_text(glyph)?,
+                shape.left as f64 + offset[0] as f64,
+                shape.ascent as f64 + offset[1] as f64,
+            )
+            .map_err(|_| {
+                GlyphAtlasError::DomError("Could render text to canvas context.".to_string())
+            })?;
+
+        let image_data = self
+            .canvas_context
+            .get_image_data(0., 0., width as f64, height as f64)
+            .map_err(|_| {
+                GlyphAtlasError::DomError(
+                    "Could not get image data from canvas context.".to_string(),
+                )
+            })?;
+
+        let mut bitmap = GlyphBitmap {
+            width,
+            height,
+            data: image_data.data().to_vec(),
+            is_color: false,
+        };
+        // Text is drawn in white, so anything else came from a colour font.
+        bitmap.is_color = bitmap.has_color();
+
+        Ok(bitmap)
+    }
+
+    fn invalidate_family(&mut self, family: &str) {
+        let fonts = &self.fonts;
+        self.text_widths
+            .retain(|(font, _), _| !fonts[*font].uses_family(family));
+        self.has_cluster
+            .retain(|(font, _), _| !fonts[*font].uses_family(family));
+    }
+}
+
+fn glyph_shape_from_text_metrics(metrics: &TextMetrics) -> GlyphShape {
+    let left: f64 = metrics.actual_bounding_box_left();
+    let right: f64 = metrics.actual_bounding_box_right();
+    let ascent: f64 = metrics.actual_bounding_box_ascent();
+    let descent: f64 = metrics.actual_bounding_box_descent();
+
+    GlyphShape {
+        left: left.ceil().max(0.) as u32,
+        right: right.ceil() as u32,
+        ascent: ascent.ceil() as u32 + 1, // TODO: figure out why this is necessary.
+        descent: descent.ceil() as u32,
+        occupied_width: metrics.width() as f32,
+    }
+}
@@ -3,6 +3,7 @@ pub enum GlyphAtlasError {
     WebGlShaderInfoLog(String),
     WebGlProgramInfoLog(String),
     DomError(String),
+    FontError(String),
     InternalError(String),
 }
 
@@ -13,6 +14,7 @@ impl std::fmt::Display for GlyphAtlasError {
             Self::WebGlProgramInfoLog(st) => write!(f, "WebGL Error linking program: {}", &st),
             Self::WebGlShaderInfoLog(st) => write!(f, "WebGL Error compiling shader: {}", &st),
             Self::DomError(st) => write!(f, "Error interacting with document: {}", &st),
+            Self::FontError(st) => write!(f, "Font error: {}", &st),
             Self::InternalError(st) => write!(f, "Internal webgl2-glyph-atlas error: {}", &st),
         }
     }
@@ -1,18 +1,299 @@
-#[derive(Hash, PartialEq, Eq, Clone)]
+use std::hash::{Hash, Hasher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontVariant {
+    Normal,
+    SmallCaps,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FontStretch {
+    UltraCondensed,
+    ExtraCondensed,
+    Condensed,
+    SemiCondensed,
+    Normal,
+    SemiExpanded,
+    Expanded,
+    ExtraExpanded,
+    UltraExpanded,
+}
+
+impl FontStretch {
+    fn as_css(self) -> &'static str {
+        match self {
+            FontStretch::UltraCondensed => "ultra-condensed",
+            FontStretch::ExtraCondensed => "extra-condensed",
+            FontStretch::Condensed => "condensed",
+            FontStretch::SemiCondensed => "semi-condensed",
+            FontStretch::Normal => "normal",
+            FontStretch::SemiExpanded => "semi-expanded",
+            FontStretch::Expanded => "expanded",
+            FontStretch::ExtraExpanded => "extra-expanded",
+            FontStretch::UltraExpanded => "ultra-expanded",
+        }
+    }
+}
+
+/// Family names that refer to a kind of font rather than a font, and must not
+/// be quoted.
+const GENERIC_FAMILIES: &[&str] = &[
+    "serif",
+    "sans-serif",
+    "monospace",
+    "cursive",
+    "fantasy",
+    "system-ui",
+    "ui-serif",
+    "ui-sans-serif",
+    "ui-monospace",
+    "ui-rounded",
+    "emoji",
+    "math",
+    "fangsong",
+];
+
+/// A list of font families along with the properties that select a face of
+/// them and the size to draw it at, like the CSS `font` shorthand. Each glyph
+/// is taken from the first family that has it.
+///
+/// ```ignore
+/// let heading = Font::new("Open Sans", 13.5)
+///     .fallback("Noto Sans JP")
+///     .with_weight(600)
+///     .with_style(FontStyle::Italic);
+/// ```
+#[derive(Clone, Debug)]
 pub struct Font {
-    name: String,
-    size: u8,
+    families: Vec<String>,
+    size: f32,
+    weight: u16,
+    style: FontStyle,
+    variant: FontVariant,
+    stretch: FontStretch,
 }
 
 impl Font {
-    pub fn as_canvas_string(&self) -> String {
-        format!("{}px {}", self.size, self.name)
+    /// A font of the given family and size in pixels, with normal weight,
+    /// style, variant and stretch.
+    pub fn new(name: &str, size: f32) -> Self {
+        Font {
+            families: vec![name.to_string()],
+            size,
+            weight: 400,
+            style: FontStyle::Normal,
+            variant: FontVariant::Normal,
+            stretch: FontStretch::Normal,
+        }
+    }
+
+    /// Adds a family to take glyphs from when the families before it don't have
+    /// them.
+    pub fn fallback(mut self, name: &str) -> Self {
+        self.families.push(name.to_string());
+        self
+    }
+
+    /// Sets the weight, from 1 to 1000. 400 is normal and 700 is bold.
+    pub fn with_weight(mut self, weight: u16) -> Self {
+        self.weight = weight.clamp(1, 1000);
+        self
     }
 
-    pub fn new(name: &str, size: u8) -> Self {
+    pub fn bold(self) -> Self {
+        self.with_weight(700)
+    }
+
+    pub fn with_style(mut self, style: FontStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn italic(self) -> Self {
+        self.with_style(FontStyle::Italic)
+    }
+
+    pub fn with_variant(mut self, variant: FontVariant) -> Self {
+        self.variant = variant;
+        self
+    }
+
+    pub fn small_caps(self) -> Self {
+        self.with_variant(FontVariant::SmallCaps)
+    }
+
+    pub fn with_stretch(mut self, stretch: FontStretch) -> Self {
+        self.stretch = stretch;
+        self
+    }
+
+    /// Sets the size in pixels.
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The same font with only the family at the given index of `families`.
+    pub fn face(&self, index: usize) -> Self {
         Font {
-            name: name.to_string(),
-            size,
+            families: vec![self.families[index].clone()],
+            ..self.clone()
+        }
+    }
+
+    /// The font as a CSS `font` shorthand, e.g.
+    /// `italic 700 13.5px "Open Sans", serif`.
+    pub fn as_canvas_string(&self) -> String {
+        let mut css = String::new();
+
+        match self.style {
+            FontStyle::Normal => (),
+            FontStyle::Italic => css.push_str("italic "),
+            FontStyle::Oblique => css.push_str("oblique "),
         }
+        if self.variant == FontVariant::SmallCaps {
+            css.push_str("small-caps ");
+        }
+        if self.weight != 400 {
+            css.push_str(&format!("{} ", self.weight));
+        }
+        if self.stretch != FontStretch::Normal {
+            css.push_str(self.stretch.as_css());
+            css.push(' ');
+        }
+        let families: Vec<String> = self
+            .families
+            .iter()
+            .map(|name| quote_family(name))
+            .collect();
+        css.push_str(&format!("{}px {}", self.size, families.join(", ")));
+
+        css
+    }
+
+    /// The first family of the font.
+    pub fn name(&self) -> &str {
+        &self.families[0]
+    }
+
+    pub fn families(&self) -> &[String] {
+        &self.families
+    }
+
+    pub(crate) fn uses_family(&self, family: &str) -> bool {
+        self.families.iter().any(|name| name == family)
+    }
+
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn style(&self) -> FontStyle {
+        self.style
+    }
+
+    pub fn variant(&self) -> FontVariant {
+        self.variant
+    }
+
+    pub fn stretch(&self) -> FontStretch {
+        self.stretch
+    }
+
+    /// Everything that distinguishes one font from another, with the size as
+    /// bits so that it can be hashed.
+    fn key(&self) -> (&[String], u32, u16, FontStyle, FontVariant, FontStretch) {
+        (
+            &self.families,
+            self.size.to_bits(),
+            self.weight,
+            self.style,
+            self.variant,
+            self.stretch,
+        )
+    }
+}
+
+impl PartialEq for Font {
+    fn eq(&self, other: &Font) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Font {}
+
+impl Hash for Font {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+/// Quotes a family name for use in CSS, unless it is a generic family.
+fn quote_family(name: &str) -> String {
+    if GENERIC_FAMILIES.contains(&name) {
+        return name.to_string();
+    }
+
+    format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn canvas_string() {
+        assert_eq!(
+            "40px \"Georgia\"",
+            Font::new("Georgia", 40.).as_canvas_string()
+        );
+        assert_eq!("13.5px serif", Font::new("serif", 13.5).as_canvas_string());
+        assert_eq!(
+            "12px \"Noto Sans\", \"Noto Sans JP\", sans-serif",
+            Font::new("Noto Sans", 12.)
+                .fallback("Noto Sans JP")
+                .fallback("sans-serif")
+                .as_canvas_string()
+        );
+        assert_eq!(
+            "italic small-caps 700 condensed 300px \"Open \\\"Sans\\\"\"",
+            Font::new("Open \"Sans\"", 300.)
+                .italic()
+                .small_caps()
+                .bold()
+                .with_stretch(FontStretch::Condensed)
+                .as_canvas_string()
+        );
+    }
+
+    #[test]
+    fn every_property_distinguishes_fonts() {
+        let font = Font::new("Georgia", 12.);
+        let fonts: HashSet<Font> = vec![
+            font.clone(),
+            font.clone(),
+            font.clone().with_size(12.5),
+            font.clone().bold(),
+            font.clone().italic(),
+            font.clone().small_caps(),
+            font.clone().with_stretch(FontStretch::Expanded),
+            font.clone().fallback("serif"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(7, fonts.len());
     }
 }
@@ -0,0 +1,159 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use wasm_bindgen_futures::JsFuture;
+use web_sys::FontFace;
+
+use crate::dom::get_document;
+use crate::error::{GlyphAtlasError, Result};
+use crate::log_error;
+
+struct RegisteredFace {
+    family: String,
+    face: FontFace,
+    loaded: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    faces: Vec<RegisteredFace>,
+    /// Families of faces that have finished loading, in the order they did.
+    loaded: Vec<String>,
+}
+
+/// Loads web fonts into the document and keeps track of when they finish
+/// loading.
+///
+/// Text drawn in a family whose font hasn't loaded yet is drawn with a fallback
+/// font. A renderer that uses the registry (see `Renderer::use_font_registry`)
+/// drops such glyphs from its atlas once the font loads, so that they are drawn
+/// again with the right font.
+///
+/// ```ignore
+/// let fonts = FontRegistry::new();
+/// fonts.load_url("Pacifico", "Pacifico-Regular.ttf")?;
+/// renderer.use_font_registry(&fonts);
+/// fonts.ready().await?;
+/// ```
+///
+/// Clones of a registry share its fonts.
+#[derive(Clone, Default)]
+pub struct FontRegistry {
+    inner: Rc<RefCell<Registry>>,
+}
+
+impl FontRegistry {
+    pub fn new() -> FontRegistry {
+        FontRegistry::default()
+    }
+
+    /// Starts loading a font file (e.g. TTF, OTF or WOFF2) from a URL, to be used
+    /// for the given family.
+    pub fn load_url(&self, family: &str, url: &str) -> Result<()> {
+        let source = format!(
+            "url(\"{}\")",
+            url.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let face = FontFace::new_with_str(family, &source).map_err(|_| {
+            GlyphAtlasError::FontError(format!("Could not create font face for {}.", family))
+        })?;
+
+        self.add(family, face)
+    }
+
+    /// Starts loading a font from the contents of a font file, to be used for
+    /// the given family.
+    pub fn load_bytes(&self, family: &str, data: &[u8]) -> Result<()> {
+        let face = FontFace::new_with_u8_array(family, data).map_err(|_| {
+            GlyphAtlasError::FontError(format!("Could not create font face for {}.", family))
+        })?;
+
+        self.add(family, face)
+    }
+
+    fn add(&self, family: &str, face: FontFace) -> Result<()> {
+        get_document()?.fonts().add(&face).map_err(|_| {
+            GlyphAtlasError::DomError("Could not add font face to document.".to_string())
+        })?;
+        let promise = face
+            .load()
+            .map_err(|_| GlyphAtlasError::FontError(format!("Could not load font {}.", family)))?;
+
+        let index = {
+            let mut inner = self.inner.borrow_mut();
+            inner.faces.push(RegisteredFace {
+                family: family.to_string(),
+                face,
+                loaded: false,
+            });
+            inner.faces.len() - 1
+        };
+
+        // Record the load even if nobody waits for `ready`.
+        let registry = self.clone();
+        let family = family.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            match JsFuture::from(promise).await {
+                Ok(_) => registry.mark_loaded(index),
+                Err(_) => log_error(GlyphAtlasError::FontError(format!(
+                    "Could not load font {}.",
+                    family
+                ))),
+            }
+        });
+
+        Ok(())
+    }
+
+    fn mark_loaded(&self, index: usize) {
+        let mut inner = self.inner.borrow_mut();
+        let face = &mut inner.faces[index];
+        if face.loaded {
+            return;
+        }
+
+        face.loaded = true;
+        let family = face.family.clone();
+        inner.loaded.push(family);
+    }
+
+    /// Whether a font has finished loading for the given family.
+    pub fn is_loaded(&self, family: &str) -> bool {
+        self.inner
+            .borrow()
+            .loaded
+            .iter()
+            .any(|loaded| loaded == family)
+    }
+
+    /// Waits until every font added so far has loaded. Fails if any of them
+    /// can't be loaded.
+    pub async fn ready(&self) -> Result<()> {
+        let pending: Vec<(usize, String, js_sys::Promise)> = {
+            let inner = self.inner.borrow();
+            let mut pending = Vec::new();
+            for (index, face) in inner.faces.iter().enumerate() {
+                let promise = face.face.loaded().map_err(|_| {
+                    GlyphAtlasError::FontError(format!("Could not load font {}.", face.family))
+                })?;
+                pending.push((index, face.family.clone(), promise));
+            }
+            pending
+        };
+
+        for (index, family, promise) in pending {
+            JsFuture::from(promise).await.map_err(|_| {
+                GlyphAtlasError::FontError(format!("Could not load font {}.", family))
+            })?;
+            self.mark_loaded(index);
+        }
+
+        Ok(())
+    }
+
+    /// Families that finished loading, in the order they did, skipping the first
+    /// `seen`.
+    pub(crate) fn loaded_families(&self, seen: usize) -> Vec<String> {
+        self.inner.borrow().loaded[seen..].to_vec()
+    }
+}
@@ -1,110 +1,444 @@
 use std::collections::HashMap;
 
-use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, ImageData, TextMetrics};
-use crate::dom::get_document;
-
-const TEXTURE_SIZE: u32 = 256;
 use crate::error::{GlyphAtlasError, Result};
+use crate::options::{AtlasOptions, GlyphMode};
 use crate::packing::{PackingNode, RectSize};
+use crate::rasterizer::{
+    FontMetrics, GlyphBitmap, GlyphId, GlyphRasterizer, GlyphShape, ShapedGlyph,
+};
+use crate::sdf::signed_distance_field;
 use crate::Font;
 
 pub type FontIndex = usize;
 
-#[derive(Eq, PartialEq, Hash)]
-struct GlyphSpec(pub char, pub FontIndex);
+/// A glyph and the font it was rasterized in, and its subpixel bin. The font has
+/// only the family the glyph was actually taken from, so a glyph found through a
+/// fallback family is stored once however many fonts fall back to that family.
+#[derive(Eq, PartialEq, Hash, Clone, Copy)]
+struct GlyphSpec(pub GlyphId, pub FontIndex, pub [u8; 2]);
+
+/// A glyph to prepare or look up in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasGlyph {
+    pub glyph: GlyphId,
+    /// Index in `Font::families` of the family the glyph comes from.
+    pub face: usize,
+    /// Horizontal and vertical subpixel offset the glyph is rasterized at, in
+    /// multiples of `1 / AtlasOptions::subpixel_bins`.
+    pub subpixel_bin: [u8; 2],
+}
+
+#[cfg(test)]
+impl AtlasGlyph {
+    /// A glyph without a subpixel offset.
+    pub fn new(glyph: GlyphId, face: usize) -> AtlasGlyph {
+        AtlasGlyph {
+            glyph,
+            face,
+            subpixel_bin: [0, 0],
+        }
+    }
+}
 
 pub struct AtlasEntry {
+    /// Index of the page (texture array layer) the glyph is on.
+    pub page: usize,
     upper_left: [u32; 2],
+    /// Size of the rect reserved for this glyph in the packing, including padding.
+    packed_size: RectSize,
+    /// Value of `GlyphAtlas::frame` when this glyph was last prepared.
+    last_used: u64,
     pub glyph_shape: GlyphShape,
+    /// Whether the glyph is drawn in its own colours rather than tinted.
+    pub is_color: bool,
 }
 
 impl AtlasEntry {
-    pub fn texture_scaled_bounds(&self) -> ([f32; 2], [f32; 2]) {
+    pub fn texture_scaled_bounds(&self, atlas_size: (u32, u32)) -> ([f32; 2], [f32; 2]) {
+        let (width, height) = (atlas_size.0 as f32, atlas_size.1 as f32);
+
         let upper_left = [
-            self.upper_left[0] as f32 / TEXTURE_SIZE as f32,
-            (self.upper_left[1] as f32 + 0.5) / TEXTURE_SIZE as f32,
+            self.upper_left[0] as f32 / width,
+            (self.upper_left[1] as f32 + 0.5) / height,
         ];
 
         let lower_right = [
-            (self.upper_left[0] + self.glyph_shape.glyph_width()) as f32 / TEXTURE_SIZE as f32,
-            ((self.upper_left[1] + self.glyph_shape.height()) as f32 + 0.5) / TEXTURE_SIZE as f32,
+            (self.upper_left[0] + self.glyph_shape.glyph_width()) as f32 / width,
+            ((self.upper_left[1] + self.glyph_shape.height()) as f32 + 0.5) / height,
         ];
 
         (upper_left, lower_right)
     }
 }
 
-pub struct GlyphAtlas {
+/// A region of an atlas page which has changed since it was last uploaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub page: usize,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DirtyRect {
+    /// Returns the smallest rect on the same page containing both rects.
+    fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+
+        DirtyRect {
+            page: self.page,
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        }
+    }
+}
+
+/// A single layer of the atlas, stored as premultiplied RGBA pixels row by row.
+struct AtlasPage {
     packing: PackingNode,
-    canvas_context: CanvasRenderingContext2d,
+    width: u32,
+    pixels: Vec<u8>,
+}
+
+impl AtlasPage {
+    fn new(width: u32, height: u32) -> AtlasPage {
+        AtlasPage {
+            packing: PackingNode::new(width, height),
+            width,
+            pixels: vec![0; (width * height * 4) as usize],
+        }
+    }
+
+    /// Byte range of `width` pixels in the given row, starting at column `x`.
+    fn row_range(&self, x: u32, y: u32, width: u32) -> std::ops::Range<usize> {
+        let start = ((y * self.width + x) * 4) as usize;
+        start..start + (width * 4) as usize
+    }
+
+    fn blit(&mut self, x: u32, y: u32, bitmap: &GlyphBitmap) {
+        let row_bytes = (bitmap.width * 4) as usize;
+
+        for row in 0..bitmap.height {
+            let source = &bitmap.data[row as usize * row_bytes..][..row_bytes];
+            let range = self.row_range(x, y + row, bitmap.width);
+            self.pixels[range].copy_from_slice(source);
+        }
+    }
+
+    fn clear(&mut self, x: u32, y: u32, size: RectSize) {
+        for row in y..y + size.height {
+            let range = self.row_range(x, row, size.width);
+            self.pixels[range].iter_mut().for_each(|byte| *byte = 0);
+        }
+    }
+
+    fn grow(&mut self, old_height: u32, width: u32, height: u32) {
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        let old_row_bytes = (self.width * 4) as usize;
+        let row_bytes = (width * 4) as usize;
+
+        for row in 0..old_height as usize {
+            pixels[row * row_bytes..][..old_row_bytes]
+                .copy_from_slice(&self.pixels[row * old_row_bytes..][..old_row_bytes]);
+        }
+
+        let packing = std::mem::replace(&mut self.packing, PackingNode::new(0, 0));
+        self.packing = packing.grow(width, height);
+        self.width = width;
+        self.pixels = pixels;
+    }
+}
+
+/// A set of equally-sized pages holding rasterized glyphs, which the renderer
+/// mirrors as the layers of a texture array.
+pub struct GlyphAtlas {
+    rasterizer: Box<dyn GlyphRasterizer>,
+    pages: Vec<AtlasPage>,
+    options: AtlasOptions,
+    width: u32,
+    height: u32,
     font_to_index: HashMap<Font, FontIndex>,
     fonts: Vec<Font>, // TODO: ugh
+    /// Metrics of each font, fetched the first time they are needed.
+    font_metrics: Vec<Option<FontMetrics>>,
     characters: HashMap<GlyphSpec, AtlasEntry>,
     needed: HashMap<GlyphSpec, GlyphShape>,
+    /// Bounding rect of the regions of each page modified during `prepare_glyphs`.
+    dirty: Vec<Option<DirtyRect>>,
+    /// Incremented on every call to `prepare_glyphs`. Glyphs not used in the current
+    /// frame may be evicted to make space for new ones.
+    frame: u64,
+    /// Glyphs not used in the current frame, most recently used first, collected
+    /// the first time one has to be evicted in the frame.
+    stale: Option<Vec<GlyphSpec>>,
+    /// Incremented whenever glyphs that may still be in use are removed, or the
+    /// texture coordinates of every glyph change because the pages grew, so that
+    /// quads built in an earlier epoch may be out of date.
+    epoch: u64,
+    /// Number of atlas pixels per logical pixel that bitmap glyphs are
+    /// rasterized at.
+    scale: f32,
 }
 
 impl GlyphAtlas {
-    pub fn new() -> Result<GlyphAtlas> {
-        let document = get_document()?;
-
-        let canvas = document
-            .create_element("canvas")
-            .map_err(|_| {
-                GlyphAtlasError::DomError("Could not construct canvas element.".to_string())
-            })?
-            .dyn_into::<web_sys::HtmlCanvasElement>()
-            .map_err(|_| GlyphAtlasError::DomError("Could not cast canvas element.".to_string()))?;
-
-        canvas
-            .set_attribute("width", &TEXTURE_SIZE.to_string())
-            .map_err(|_| GlyphAtlasError::DomError("Could not set attribute.".to_string()))?;
-        canvas
-            .set_attribute("height", &TEXTURE_SIZE.to_string())
-            .map_err(|_| GlyphAtlasError::DomError("Could not set attribute.".to_string()))?;
-
-        // For debugging. TODO: feature-gate this?
-        /*
-        canvas
-            .set_attribute(
-                "style",
-                "height: 512px; width: 512px; image-rendering: pixelated",
-            )
-            .unwrap();
-        document.body().unwrap().append_child(&canvas).unwrap();
-         */
-
-        let canvas_context = canvas
-            .get_context("2d")
-            .map_err(|_| GlyphAtlasError::DomError("Could not get context.".to_string()))?
-            .ok_or_else(|| {
-                GlyphAtlasError::DomError(
-                    "A non-2D context has already been requested from this canvas.".to_string(),
-                )
-            })?
-            .dyn_into::<CanvasRenderingContext2d>()
-            .map_err(|_| GlyphAtlasError::DomError("Could not cast canvas context.".to_string()))?;
-
-        let packing = PackingNode::new(TEXTURE_SIZE as u32, TEXTURE_SIZE as u32);
-
-        Ok(GlyphAtlas {
-            canvas_context,
-            packing,
+    pub fn new(options: &AtlasOptions, rasterizer: Box<dyn GlyphRasterizer>) -> GlyphAtlas {
+        let width = options.initial_width;
+        let height = options.initial_height;
+
+        GlyphAtlas {
+            rasterizer,
+            pages: vec![AtlasPage::new(width, height)],
+            options: *options,
+            width,
+            height,
             characters: HashMap::default(),
             font_to_index: HashMap::new(),
             fonts: Vec::new(),
+            font_metrics: Vec::new(),
             needed: Default::default(),
-        })
+            dirty: vec![None],
+            frame: 0,
+            stale: None,
+            epoch: 0,
+            scale: 1.,
+        }
     }
 
-    pub fn image_data(&self) -> Result<ImageData> {
-        self.canvas_context
-            .get_image_data(0., 0., TEXTURE_SIZE as f64, TEXTURE_SIZE as f64)
-            .map_err(|_| {
-                GlyphAtlasError::DomError(
-                    "Could not get image data from canvas context.".to_string(),
-                )
-            })
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the number of atlas pixels per logical pixel, e.g. the device pixel
+    /// ratio of the display. Fonts are rasterized at their size times the scale,
+    /// so glyphs rasterized at other scales stay cached under their own size.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Current width and height of each page of the atlas, in pixels.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// RGBA pixels of a page, row by row.
+    pub fn pixels(&self, page: usize) -> &[u8] {
+        &self.pages[page].pixels
+    }
+
+    /// Doubles the dimensions of every page (up to the configured maximum), keeping
+    /// every glyph already in the atlas at its current position. Returns `false` if
+    /// the pages are already at their maximum size.
+    fn grow(&mut self) -> bool {
+        let width = (self.width * 2).min(self.options.max_width).max(self.width);
+        let height = (self.height * 2)
+            .min(self.options.max_height)
+            .max(self.height);
+
+        if width == self.width && height == self.height {
+            return false;
+        }
+
+        for page in &mut self.pages {
+            page.grow(self.height, width, height);
+        }
+        self.width = width;
+        self.height = height;
+        self.epoch += 1;
+
+        true
+    }
+
+    /// Adds an empty page to the atlas. Returns `false` if the atlas already has
+    /// the maximum number of pages.
+    fn add_page(&mut self) -> bool {
+        if self.pages.len() >= self.options.max_pages as usize {
+            return false;
+        }
+
+        self.pages.push(AtlasPage::new(self.width, self.height));
+        self.dirty.push(None);
+
+        true
+    }
+
+    /// Finds space for a rect on any page, making space if necessary by evicting
+    /// unused glyphs, growing the pages, or adding a page, in that order.
+    fn allocate(&mut self, size: RectSize) -> Result<(usize, u32, u32)> {
+        loop {
+            for (page_index, page) in self.pages.iter_mut().enumerate() {
+                if let Some((x, y)) = page.packing.insert_rect(size) {
+                    return Ok((page_index, x, y));
+                }
+            }
+
+            if !self.evict_least_recently_used() && !self.grow() && !self.add_page() {
+                return Err(GlyphAtlasError::InternalError(
+                    "Ran out of space to pack rect.".to_string(),
+                ));
+            }
+        }
+    }
+
+    /// Removes the least recently used glyph that is not needed in the current frame
+    /// from the atlas, freeing its space. Returns `false` if there was no such glyph.
+    fn evict_least_recently_used(&mut self) -> bool {
+        let mut stale = self.stale.take().unwrap_or_else(|| {
+            let frame = self.frame;
+            let mut stale: Vec<(u64, GlyphSpec)> = self
+                .characters
+                .iter()
+                .filter(|(_, entry)| entry.last_used < frame)
+                .map(|(key, entry)| (entry.last_used, *key))
+                .collect();
+            stale.sort_by_key(|(last_used, _)| std::cmp::Reverse(*last_used));
+            stale.into_iter().map(|(_, key)| key).collect()
+        });
+
+        let mut evicted = false;
+        while let Some(key) = stale.pop() {
+            if self.remove_entry(key) {
+                evicted = true;
+                break;
+            }
+        }
+        self.stale = Some(stale);
+
+        evicted
+    }
+
+    /// Removes a glyph from the atlas, freeing its space.
+    fn remove_entry(&mut self, key: GlyphSpec) -> bool {
+        let entry = match self.characters.remove(&key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        let [x, y] = entry.upper_left;
+        let page = &mut self.pages[entry.page];
+        page.packing.remove_rect(x, y, entry.packed_size);
+        page.clear(x, y, entry.packed_size);
+        self.mark_dirty(entry.page, x, y, entry.packed_size);
+
+        true
+    }
+
+    /// Removes every glyph and forgets the metrics of every font that uses the
+    /// given family, so that they are fetched from the rasterizer again. Used
+    /// when the font of a family changes, e.g. when a web font finishes loading.
+    pub fn invalidate_family(&mut self, family: &str) {
+        self.rasterizer.invalidate_family(family);
+
+        for (font, metrics) in self.fonts.iter().zip(&mut self.font_metrics) {
+            if font.uses_family(family) {
+                *metrics = None;
+            }
+        }
+
+        let fonts = &self.fonts;
+        let keys: Vec<GlyphSpec> = self
+            .characters
+            .keys()
+            .filter(|GlyphSpec(_, font_idx, _)| fonts[*font_idx].uses_family(family))
+            .copied()
+            .collect();
+        for key in keys {
+            self.remove_entry(key);
+        }
+        self.epoch += 1;
+    }
+
+    fn mark_dirty(&mut self, page: usize, x: u32, y: u32, size: RectSize) {
+        // Nothing to upload for empty glyphs such as spaces, and WebGL rejects
+        // reading back an empty region.
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+
+        let rect = DirtyRect {
+            page,
+            x,
+            y,
+            width: size.width,
+            height: size.height,
+        };
+
+        let dirty = &mut self.dirty[page];
+        *dirty = Some(match dirty {
+            Some(dirty) => dirty.union(&rect),
+            None => rect,
+        });
+    }
+
+    /// The font glyphs of the given font are rasterized in. In signed distance
+    /// field mode, every size of a face shares one set of glyphs.
+    fn atlas_font(&self, font: &Font) -> Font {
+        match self.options.glyph_mode {
+            GlyphMode::Bitmap if self.scale == 1. => font.clone(),
+            GlyphMode::Bitmap => font.clone().with_size(font.size() * self.scale),
+            GlyphMode::SignedDistanceField { size, .. } => font.clone().with_size(size as f32),
+        }
+    }
+
+    /// The font glyphs taken from the family at index `face` of the given font
+    /// are rasterized in.
+    fn face_font(&self, font: &Font, face: usize) -> Font {
+        self.atlas_font(font).face(face)
+    }
+
+    /// Number of horizontal and vertical subpixel positions glyphs are rasterized
+    /// at, or `None` if glyphs are drawn at any position without being snapped to
+    /// pixels, as signed distance fields are.
+    pub fn subpixel_bins(&self) -> Option<[u8; 2]> {
+        match self.options.glyph_mode {
+            GlyphMode::Bitmap => Some(self.options.subpixel_bins),
+            GlyphMode::SignedDistanceField { .. } => None,
+        }
+    }
+
+    /// Grows a measured shape so that the glyph still fits when it is moved right
+    /// or down by less than a pixel to rasterize it at a subpixel offset.
+    fn with_subpixel_room(&self, shape: GlyphShape) -> GlyphShape {
+        let [horizontal, vertical] = self.subpixel_bins().unwrap_or([1, 1]);
+
+        GlyphShape {
+            right: shape.right + (horizontal > 1) as u32,
+            descent: shape.descent + (vertical > 1) as u32,
+            ..shape
+        }
+    }
+
+    /// Reverses `with_subpixel_room`.
+    fn without_subpixel_room(&self, shape: GlyphShape) -> GlyphShape {
+        let [horizontal, vertical] = self.subpixel_bins().unwrap_or([1, 1]);
+
+        GlyphShape {
+            right: shape.right.saturating_sub((horizontal > 1) as u32),
+            descent: shape.descent.saturating_sub((vertical > 1) as u32),
+            ..shape
+        }
+    }
+
+    /// Factor by which glyphs in the atlas have to be scaled to be drawn in the
+    /// given font, in logical pixels.
+    pub fn glyph_scale(&self, font: &Font) -> f32 {
+        match self.options.glyph_mode {
+            GlyphMode::Bitmap => 1. / self.scale,
+            GlyphMode::SignedDistanceField { size, .. } => font.size() / size as f32,
+        }
     }
 
     fn font_to_index(&mut self, font: &Font) -> usize {
@@ -113,142 +447,571 @@ impl GlyphAtlas {
         } else {
             let len = self.fonts.len();
             self.fonts.push(font.clone());
+            self.font_metrics.push(None);
             self.font_to_index.insert(font.clone(), len);
 
             len
         }
     }
 
-    pub fn prepare_text(&mut self, strings: Vec<(&str, &Font)>) -> Result<bool> {
-        self.needed.clear();
-        for (text, font) in strings {
-            self.canvas_context.set_font(&font.as_canvas_string());
+    /// Vertical metrics of the given font, in pixels.
+    pub fn font_metrics(&mut self, font: &Font) -> Result<FontMetrics> {
+        let scale = self.glyph_scale(font);
+        let font = self.atlas_font(font);
+        let font_idx = self.font_to_index(&font);
+
+        let metrics = match self.font_metrics[font_idx] {
+            Some(metrics) => metrics,
+            None => {
+                let metrics = self.rasterizer.font_metrics(&font)?;
+                self.font_metrics[font_idx] = Some(metrics);
+                metrics
+            }
+        };
 
-            let font_idx = self.font_to_index(font);
+        Ok(metrics.scale(scale))
+    }
 
-            for ch in text.chars() {
-                let key = GlyphSpec(ch, font_idx);
-                if !self.characters.contains_key(&key) && !self.needed.contains_key(&key) {
-                    let st: String = ch.to_string();
-                    let metrics: TextMetrics =
-                        self.canvas_context.measure_text(&st).map_err(|_| {
-                            GlyphAtlasError::DomError("Could not measure text.".to_string())
-                        })?;
+    /// Shapes text into the glyphs to prepare and draw it with. Positions are
+    /// those of the atlas's glyphs, so they have to be multiplied by
+    /// `glyph_scale`.
+    pub fn shape_text(&mut self, text: &str, font: &Font) -> Result<Vec<ShapedGlyph>> {
+        let font = self.atlas_font(font);
+        self.rasterizer.shape_text(text, &font)
+    }
 
-                    let glyph_shape = GlyphShape::from_text_metrics(&metrics);
+    /// Returns the bounds of a glyph without adding it to the atlas. The bounds
+    /// are those of the atlas's glyph, so they have to be multiplied by
+    /// `glyph_scale`.
+    pub fn measure_glyph(
+        &mut self,
+        glyph: GlyphId,
+        face: usize,
+        font: &Font,
+    ) -> Result<GlyphShape> {
+        let font = self.face_font(font, face);
+
+        let entry = self
+            .font_to_index
+            .get(&font)
+            .and_then(|&font_idx| self.characters.get(&GlyphSpec(glyph, font_idx, [0, 0])));
+        match (entry, self.options.glyph_mode) {
+            (Some(entry), GlyphMode::Bitmap) => {
+                Ok(self.without_subpixel_room(entry.glyph_shape.clone()))
+            }
+            (Some(entry), GlyphMode::SignedDistanceField { spread, .. }) => {
+                Ok(entry.glyph_shape.without_margin(spread))
+            }
+            (None, _) => self.rasterizer.measure_glyph(glyph, &font),
+        }
+    }
 
+    /// Makes sure every glyph in the given runs is in the atlas, rasterizing any
+    /// that are missing. Returns the regions of the atlas that were modified, at
+    /// most one per page.
+    pub fn prepare_glyphs(&mut self, runs: Vec<(&[AtlasGlyph], &Font)>) -> Result<Vec<DirtyRect>> {
+        self.needed.clear();
+        self.frame += 1;
+        self.stale = None;
+        for (glyphs, font) in runs {
+            let face_indices: Vec<FontIndex> = (0..font.families().len())
+                .map(|face| {
+                    let face_font = self.face_font(font, face);
+                    self.font_to_index(&face_font)
+                })
+                .collect();
+
+            for glyph in glyphs {
+                let font_idx = face_indices[glyph.face];
+                let key = GlyphSpec(glyph.glyph, font_idx, glyph.subpixel_bin);
+                if let Some(entry) = self.characters.get_mut(&key) {
+                    entry.last_used = self.frame;
+                } else if !self.needed.contains_key(&key) {
+                    let glyph_shape = self
+                        .rasterizer
+                        .measure_glyph(glyph.glyph, &self.fonts[font_idx])?;
+                    let glyph_shape = self.with_subpixel_room(glyph_shape);
                     self.needed.insert(key, glyph_shape);
                 }
             }
         }
 
-        if self.needed.len() == 0 {
-            return Ok(false);
+        if self.needed.is_empty() {
+            return Ok(Vec::new());
         }
 
         let mut needed: Vec<(GlyphSpec, GlyphShape)> = self.needed.drain().collect();
 
         needed.sort_by(|(_, s1), (_, s2)| s2.size().area().cmp(&s1.size().area()));
 
-        for (GlyphSpec(ch, font_id), glyph_shape) in needed.into_iter() {
+        for (GlyphSpec(glyph, font_id, subpixel_bin), glyph_shape) in needed.into_iter() {
             let size = glyph_shape.size();
+            let [horizontal, vertical] = self.subpixel_bins().unwrap_or([1, 1]);
+            let offset = [
+                subpixel_bin[0] as f32 / horizontal as f32,
+                subpixel_bin[1] as f32 / vertical as f32,
+            ];
+
+            let mut bitmap = self.rasterizer.rasterize_glyph(
+                glyph,
+                &self.fonts[font_id],
+                &glyph_shape,
+                offset,
+            )?;
+            if bitmap.width != size.width || bitmap.height != size.height {
+                return Err(GlyphAtlasError::InternalError(
+                    "Rasterizer returned a bitmap of the wrong size.".to_string(),
+                ));
+            }
 
-            let (x, y) = self.packing.insert_rect(size).ok_or_else(|| {
-                GlyphAtlasError::InternalError("Ran out of space to pack rect.".to_string())
-            })?;
-
-            // For debugging. TODO: feature-gate this?
-            /*
-            self.canvas_context
-                .set_stroke_style(&wasm_bindgen::JsValue::from("#ff00ff"));
-            self.canvas_context.stroke_rect(x as f64 + 0.5, y as f64 + 0.5, size.width as f64 - 1.0, size.height as f64 - 1.0);
-                */
-
-            self.canvas_context.save();
-
-            self.canvas_context
-                .set_font(&self.fonts[font_id].as_canvas_string());
-
-            self.canvas_context
-                .rect(x as f64, y as f64, size.width as f64, size.height as f64);
-            self.canvas_context.clip();
+            bitmap.premultiply();
+
+            let (glyph_shape, bitmap) = match self.options.glyph_mode {
+                GlyphMode::Bitmap => (glyph_shape, bitmap),
+                GlyphMode::SignedDistanceField { spread, .. } => {
+                    // Leave room around the glyph for the distance field to fall off.
+                    // Only the outline is kept, so colour glyphs are tinted like any
+                    // other.
+                    let bitmap = bitmap.with_margin(spread);
+                    (
+                        glyph_shape.with_margin(spread),
+                        signed_distance_field(&bitmap, spread),
+                    )
+                }
+            };
 
-            self.canvas_context
-                .fill_text(&ch.to_string(), x as f64, (y + glyph_shape.ascent) as f64)
-                .map_err(|_| {
-                    GlyphAtlasError::DomError("Could render text to canvas context.".to_string())
-                })?;
+            let size = glyph_shape.size();
+            let padded_size = RectSize {
+                width: size.width + self.options.padding,
+                height: size.height + self.options.padding,
+            };
 
-            self.canvas_context.restore();
+            let (page, x, y) = self.allocate(padded_size)?;
+            self.pages[page].blit(x, y, &bitmap);
+            self.mark_dirty(page, x, y, padded_size);
 
             self.characters.insert(
-                GlyphSpec(ch, font_id),
+                GlyphSpec(glyph, font_id, subpixel_bin),
                 AtlasEntry {
                     glyph_shape,
+                    is_color: bitmap.is_color,
+                    page,
                     upper_left: [x, y],
+                    packed_size: padded_size,
+                    last_used: self.frame,
                 },
             );
         }
 
-        Ok(true)
+        Ok(self.dirty.iter_mut().filter_map(Option::take).collect())
     }
 
-    pub fn get_entry(&self, c: char, font: &Font) -> Result<&AtlasEntry> {
-        let font_idx: FontIndex = *self.font_to_index.get(font).ok_or_else(|| {
+    pub fn get_entry(&self, glyph: AtlasGlyph, font: &Font) -> Result<&AtlasEntry> {
+        let font = self.face_font(font, glyph.face);
+        let font_idx: FontIndex = *self.font_to_index.get(&font).ok_or_else(|| {
             GlyphAtlasError::InternalError(
                 "Attempted to render font that is not in index.".to_string(),
             )
         })?;
-        let ch = self
-            .characters
-            .get(&GlyphSpec(c, font_idx))
+        self.characters
+            .get(&GlyphSpec(glyph.glyph, font_idx, glyph.subpixel_bin))
             .ok_or_else(|| {
                 GlyphAtlasError::InternalError(
                     "Attempted to render glyph that is not in index.".to_string(),
                 )
-            })?;
-
-        Ok(ch)
+            })
     }
 }
 
-#[derive(Debug)]
-pub struct GlyphShape {
-    pub left: u32,
-    pub right: u32,
-    pub ascent: u32,
-    pub descent: u32,
-    pub occupied_width: f32,
-}
-
-impl GlyphShape {
-    pub fn from_text_metrics(metrics: &TextMetrics) -> GlyphShape {
-        let left: f64 = metrics.actual_bounding_box_left();
-        let right: f64 = metrics.actual_bounding_box_right();
-        let ascent: f64 = metrics.actual_bounding_box_ascent();
-        let descent: f64 = metrics.actual_bounding_box_descent();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterizer::testing::BoxRasterizer;
+
+    fn atlas(width: u32, height: u32, max_width: u32, max_height: u32) -> GlyphAtlas {
+        let options = AtlasOptions {
+            initial_width: width,
+            initial_height: height,
+            max_width,
+            max_height,
+            max_pages: 1,
+            ..AtlasOptions::default()
+        };
+
+        GlyphAtlas::new(&options, Box::new(BoxRasterizer::new(8, 6, 2, 9.)))
+    }
 
-        GlyphShape {
-            left: (-left).ceil() as u32,
-            right: right.ceil() as u32,
-            ascent: ascent.ceil() as u32 + 1, // TODO: figure out why this is necessary.
-            descent: descent.ceil() as u32,
-            occupied_width: metrics.width() as f32,
+    /// Shapes and prepares the given strings.
+    fn prepare_text(atlas: &mut GlyphAtlas, strings: Vec<(&str, &Font)>) -> Result<Vec<DirtyRect>> {
+        let mut shaped = Vec::new();
+        for (text, font) in strings {
+            let glyphs: Vec<AtlasGlyph> = atlas
+                .shape_text(text, font)?
+                .iter()
+                .map(|shaped| AtlasGlyph::new(shaped.glyph, shaped.face))
+                .collect();
+            shaped.push((glyphs, font));
         }
+
+        atlas.prepare_glyphs(
+            shaped
+                .iter()
+                .map(|(glyphs, font)| (glyphs.as_slice(), *font))
+                .collect(),
+        )
     }
 
-    pub fn glyph_width(&self) -> u32 {
-        self.left + self.right
+    /// Returns the red channel of the pixel at the given position.
+    fn red_at(atlas: &GlyphAtlas, page: usize, x: u32, y: u32) -> u8 {
+        atlas.pixels(page)[((y * atlas.width + x) * 4) as usize]
     }
 
-    pub fn height(&self) -> u32 {
-        self.ascent + self.descent
+    #[test]
+    fn texture_scaled_bounds() {
+        let entry = AtlasEntry {
+            page: 0,
+            upper_left: [16, 32],
+            packed_size: RectSize {
+                width: 8,
+                height: 8,
+            },
+            last_used: 0,
+            glyph_shape: GlyphShape {
+                left: 0,
+                right: 8,
+                ascent: 6,
+                descent: 2,
+                occupied_width: 9.,
+            },
+            is_color: false,
+        };
+
+        let (upper_left, lower_right) = entry.texture_scaled_bounds((64, 128));
+
+        assert_eq!([0.25, 32.5 / 128.], upper_left);
+        assert_eq!([0.375, 40.5 / 128.], lower_right);
     }
 
-    pub fn size(&self) -> RectSize {
-        RectSize {
-            width: self.glyph_width(),
-            height: self.height(),
+    #[test]
+    fn glyphs_are_rasterized_once() {
+        let mut atlas = atlas(32, 32, 32, 32);
+        let font = Font::new("Test", 10.);
+
+        let dirty = prepare_text(&mut atlas, vec![("abba", &font)]).unwrap();
+        assert_eq!(2, atlas.characters.len());
+        assert_eq!(1, dirty.len());
+        assert_eq!(0, dirty[0].page);
+
+        let dirty = prepare_text(&mut atlas, vec![("ab", &font), ("ba", &font)]).unwrap();
+        assert_eq!(2, atlas.characters.len());
+        assert!(dirty.is_empty());
+
+        // The same character in another font is a separate glyph.
+        prepare_text(&mut atlas, vec![("a", &Font::new("Test", 11.))]).unwrap();
+        assert_eq!(3, atlas.characters.len());
+    }
+
+    #[test]
+    fn glyph_pixels_are_copied_into_page() {
+        let mut atlas = atlas(32, 32, 32, 32);
+        let font = Font::new("Test", 10.);
+
+        let dirty = prepare_text(&mut atlas, vec![("a", &font)]).unwrap();
+        let entry = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap();
+        let [x, y] = entry.upper_left;
+
+        assert_eq!(
+            vec![DirtyRect {
+                page: 0,
+                x,
+                y,
+                width: 8,
+                height: 8
+            }],
+            dirty
+        );
+        assert_eq!(b'a', red_at(&atlas, 0, x, y));
+        assert_eq!(b'a', red_at(&atlas, 0, x + 7, y + 7));
+    }
+
+    #[test]
+    fn empty_glyphs_leave_nothing_dirty() {
+        let mut atlas = GlyphAtlas::new(
+            &AtlasOptions::default(),
+            Box::new(BoxRasterizer::new(0, 6, 2, 9.)),
+        );
+        let font = Font::new("Test", 10.);
+
+        let dirty = prepare_text(&mut atlas, vec![("  ", &font)]).unwrap();
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn atlas_grows_when_full() {
+        let mut atlas = atlas(16, 16, 64, 64);
+        let font = Font::new("Test", 10.);
+
+        prepare_text(&mut atlas, vec![("abcd", &font)]).unwrap();
+        assert_eq!((16, 16), atlas.size());
+        let [x, y] = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap()
+            .upper_left;
+
+        prepare_text(&mut atlas, vec![("abcde", &font)]).unwrap();
+        assert_eq!((32, 32), atlas.size());
+
+        // Existing glyphs keep their position and pixels.
+        assert_eq!(
+            [x, y],
+            atlas
+                .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+                .unwrap()
+                .upper_left
+        );
+        assert_eq!(b'a', red_at(&atlas, 0, x, y));
+    }
+
+    #[test]
+    fn unused_glyphs_are_evicted_before_growing() {
+        let mut atlas = atlas(16, 16, 64, 64);
+        let font = Font::new("Test", 10.);
+
+        prepare_text(&mut atlas, vec![("abcd", &font)]).unwrap();
+        let [x, y] = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap()
+            .upper_left;
+        prepare_text(&mut atlas, vec![("bcd", &font)]).unwrap();
+
+        let dirty = prepare_text(&mut atlas, vec![("bcde", &font)]).unwrap();
+
+        assert_eq!((16, 16), atlas.size());
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .is_err());
+        assert_eq!(
+            [x, y],
+            atlas
+                .get_entry(AtlasGlyph::new('e' as GlyphId, 0), &font)
+                .unwrap()
+                .upper_left
+        );
+        assert_eq!(b'e', red_at(&atlas, 0, x, y));
+        assert_eq!(1, dirty.len());
+    }
+
+    #[test]
+    fn least_recently_used_glyphs_are_evicted_first() {
+        let mut atlas = atlas(16, 16, 64, 64);
+        let font = Font::new("Test", 10.);
+        let has_glyph = |atlas: &GlyphAtlas, ch: char| {
+            atlas
+                .get_entry(AtlasGlyph::new(ch as GlyphId, 0), &font)
+                .is_ok()
+        };
+
+        prepare_text(&mut atlas, vec![("abcd", &font)]).unwrap();
+        prepare_text(&mut atlas, vec![("bcd", &font)]).unwrap();
+        prepare_text(&mut atlas, vec![("cd", &font)]).unwrap();
+
+        // Two glyphs have to go: the ones used longest ago.
+        prepare_text(&mut atlas, vec![("ef", &font)]).unwrap();
+
+        assert_eq!((16, 16), atlas.size());
+        assert!(!has_glyph(&atlas, 'a'));
+        assert!(!has_glyph(&atlas, 'b'));
+        for ch in "cdef".chars() {
+            assert!(has_glyph(&atlas, ch));
         }
     }
+
+    #[test]
+    fn pages_are_added_at_max_size() {
+        let mut atlas = atlas(16, 16, 16, 16);
+        atlas.options.max_pages = 2;
+        let font = Font::new("Test", 10.);
+
+        prepare_text(&mut atlas, vec![("abcdef", &font)]).unwrap();
+
+        assert_eq!(2, atlas.num_pages());
+        let pages: Vec<usize> = "abcdef"
+            .chars()
+            .map(|ch| {
+                atlas
+                    .get_entry(AtlasGlyph::new(ch as GlyphId, 0), &font)
+                    .unwrap()
+                    .page
+            })
+            .collect();
+        assert_eq!(4, pages.iter().filter(|&&page| page == 0).count());
+        assert_eq!(2, pages.iter().filter(|&&page| page == 1).count());
+    }
+
+    #[test]
+    fn distance_fields_of_color_glyphs_are_tinted() {
+        let mut rasterizer = BoxRasterizer::new(8, 6, 2, 9.);
+        rasterizer.color_glyphs.push('a' as GlyphId);
+        let options = AtlasOptions {
+            glyph_mode: GlyphMode::SignedDistanceField {
+                size: 10,
+                spread: 2,
+            },
+            ..AtlasOptions::default()
+        };
+        let mut atlas = GlyphAtlas::new(&options, Box::new(rasterizer));
+        let font = Font::new("Test", 10.);
+
+        prepare_text(&mut atlas, vec![("a", &font)]).unwrap();
+
+        // Only the outline is kept, so the glyph has lost its colours.
+        let entry = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap();
+        assert!(!entry.is_color);
+    }
+
+    #[test]
+    fn distance_fields_are_shared_between_sizes() {
+        let mut atlas = atlas(64, 64, 64, 64);
+        atlas.options.glyph_mode = GlyphMode::SignedDistanceField {
+            size: 20,
+            spread: 2,
+        };
+
+        prepare_text(
+            &mut atlas,
+            vec![
+                ("a", &Font::new("Test", 10.)),
+                ("a", &Font::new("Test", 40.)),
+            ],
+        )
+        .unwrap();
+        assert_eq!(1, atlas.characters.len());
+        assert_eq!(2., atlas.glyph_scale(&Font::new("Test", 40.)));
+
+        // The glyph is stored with room for the distance field to fall off.
+        let entry = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &Font::new("Test", 10.))
+            .unwrap();
+        assert_eq!(2, entry.glyph_shape.left);
+        assert_eq!(12, entry.glyph_shape.glyph_width());
+        assert_eq!(12, entry.glyph_shape.height());
+    }
+
+    #[test]
+    fn fallback_glyphs_are_stored_under_their_family() {
+        let mut rasterizer = BoxRasterizer::new(8, 6, 2, 9.);
+        rasterizer.missing_glyphs.push('b' as GlyphId);
+        let mut atlas = GlyphAtlas::new(&AtlasOptions::default(), Box::new(rasterizer));
+        let font = Font::new("Test", 10.).fallback("Fallback");
+        let other_font = Font::new("Other", 10.).fallback("Fallback");
+
+        prepare_text(&mut atlas, vec![("ab", &font), ("b", &other_font)]).unwrap();
+
+        // Both fonts take "b" from the fallback family, so it is stored once.
+        assert_eq!(2, atlas.characters.len());
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .is_ok());
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('b' as GlyphId, 1), &font)
+            .is_ok());
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('b' as GlyphId, 0), &font)
+            .is_err());
+        assert!(atlas
+            .get_entry(
+                AtlasGlyph::new('b' as GlyphId, 0),
+                &Font::new("Fallback", 10.)
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn invalidated_families_are_removed() {
+        let mut atlas = atlas(64, 64, 64, 64);
+        let font = Font::new("Test", 10.);
+        let other_font = Font::new("Other", 10.);
+        prepare_text(&mut atlas, vec![("ab", &font), ("a", &other_font)]).unwrap();
+        let [x, y] = atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap()
+            .upper_left;
+
+        atlas.invalidate_family("Test");
+
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .is_err());
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &other_font)
+            .is_ok());
+        assert_eq!(0, red_at(&atlas, 0, x, y));
+
+        // The glyphs are rasterized again when they are next needed.
+        prepare_text(&mut atlas, vec![("a", &font)]).unwrap();
+        assert!(atlas
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .is_ok());
+    }
+
+    #[test]
+    fn bitmaps_are_rasterized_at_scale() {
+        let mut atlas = atlas(64, 64, 64, 64);
+        let font = Font::new("Test", 10.);
+
+        atlas.set_scale(2.);
+        prepare_text(&mut atlas, vec![("a", &font)]).unwrap();
+        atlas.set_scale(1.);
+        prepare_text(&mut atlas, vec![("a", &font)]).unwrap();
+
+        // Each scale has its own glyph, rasterized at the scaled size.
+        assert_eq!(2, atlas.characters.len());
+        assert!(atlas.font_to_index.contains_key(&Font::new("Test", 20.)));
+        assert_eq!(1., atlas.glyph_scale(&font));
+        atlas.set_scale(2.);
+        assert_eq!(0.5, atlas.glyph_scale(&font));
+    }
+
+    #[test]
+    fn subpixel_variants_are_stored_separately() {
+        let mut atlas = atlas(64, 64, 64, 64);
+        atlas.options.subpixel_bins = [4, 1];
+        let font = Font::new("Test", 10.);
+        let at = |subpixel_bin| AtlasGlyph {
+            glyph: 'a' as GlyphId,
+            face: 0,
+            subpixel_bin,
+        };
+
+        atlas
+            .prepare_glyphs(vec![(&[at([0, 0]), at([2, 0]), at([2, 0])], &font)])
+            .unwrap();
+
+        assert_eq!(2, atlas.characters.len());
+        // There is room for the glyph to move right by up to a pixel.
+        assert_eq!(
+            9,
+            atlas
+                .get_entry(at([2, 0]), &font)
+                .unwrap()
+                .glyph_shape
+                .right
+        );
+        assert_eq!(
+            8,
+            atlas.measure_glyph('a' as GlyphId, 0, &font).unwrap().right
+        );
+    }
+
+    #[test]
+    fn error_when_out_of_space() {
+        let mut atlas = atlas(16, 16, 16, 16);
+        let font = Font::new("Test", 10.);
+
+        assert!(prepare_text(&mut atlas, vec![("abcde", &font)]).is_err());
+    }
 }
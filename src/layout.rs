@@ -0,0 +1,403 @@
+use std::ops::Range;
+
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+
+use crate::rasterizer::{FontMetrics, GlyphId, ShapedGlyph};
+use crate::style::{TextAlign, TextBaseline, TextStyle};
+
+/// A glyph positioned relative to the origin of the first line of its text,
+/// with y pointing up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedGlyph {
+    pub glyph: GlyphId,
+    /// Index in `Font::families` of the family the glyph comes from.
+    pub face: usize,
+    /// Byte offset in the text of the first character this glyph represents.
+    pub cluster: usize,
+    pub x: f32,
+    pub y: f32,
+    pub advance: f32,
+}
+
+/// Shaped text broken into lines.
+pub struct TextLayout {
+    pub glyphs: Vec<PositionedGlyph>,
+    /// Width of the widest line, not counting trailing whitespace.
+    pub width: f32,
+    pub num_lines: usize,
+    /// Distance between consecutive baselines.
+    pub line_height: f32,
+    /// Position of the first line's baseline relative to the origin.
+    pub baseline: f32,
+}
+
+/// The size of a text as it would be drawn, relative to its origin (the start
+/// of the first line's baseline), with y pointing along the renderer's
+/// [`YAxis`](crate::YAxis).
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextExtents {
+    /// Width of the widest line, not counting trailing whitespace.
+    pub width: f32,
+    pub num_lines: usize,
+    /// How far the glyphs extend above the first line's baseline.
+    pub ascent: f32,
+    /// How far the glyphs extend below the last line's baseline.
+    pub descent: f32,
+    /// Corner of the bounding box of the glyphs with the smallest coordinates.
+    pub ink_min: [f32; 2],
+    /// Corner of the bounding box of the glyphs with the largest coordinates.
+    pub ink_max: [f32; 2],
+    pub glyphs: Vec<GlyphExtents>,
+}
+
+/// Where a glyph of a measured text is drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphExtents {
+    /// Byte offset in the text of the first character this glyph represents.
+    pub cluster: usize,
+    /// Position of the glyph's origin.
+    pub x: f32,
+    pub y: f32,
+    /// How far the glyph moves the pen.
+    pub advance: f32,
+}
+
+/// Glyphs between two line break opportunities.
+struct Segment {
+    glyphs: Range<usize>,
+    /// Width of the glyphs, including trailing whitespace.
+    width: f32,
+    trailing_whitespace: f32,
+    /// Whether the line has to break after this segment.
+    mandatory_break: bool,
+}
+
+struct Line {
+    segments: Range<usize>,
+    /// Width of the segments, not counting trailing whitespace.
+    width: f32,
+}
+
+/// Breaks shaped text into lines and positions its glyphs according to the
+/// layout properties of the style. `scale` converts the shaped positions to
+/// pixels, and `metrics` are those of the font, in pixels.
+///
+/// Text is assumed to run left to right.
+pub fn layout_text(
+    text: &str,
+    glyphs: &[ShapedGlyph],
+    scale: f32,
+    metrics: &FontMetrics,
+    style: &TextStyle,
+) -> TextLayout {
+    let segments = segments(text, glyphs, scale);
+    let lines = break_lines(&segments, style.max_width);
+
+    let width = lines.iter().map(|line| line.width).fold(0., f32::max);
+    let box_width = style.max_width.unwrap_or(width);
+    let line_height = style.line_height * metrics.line_height();
+    let last_baseline = -(lines.len().saturating_sub(1) as f32) * line_height;
+    let baseline = match style.baseline {
+        TextBaseline::Top => -metrics.ascent,
+        TextBaseline::Middle => -(metrics.ascent + last_baseline - metrics.descent) / 2.,
+        TextBaseline::Alphabetic => 0.,
+        TextBaseline::Bottom => metrics.descent - last_baseline,
+    };
+    let mut positioned = Vec::with_capacity(glyphs.len());
+
+    for (line_index, line) in lines.iter().enumerate() {
+        let line_segments = &segments[line.segments.clone()];
+        let free_space = (box_width - line.width).max(0.);
+        let ends_paragraph = line_segments
+            .last()
+            .map(|segment| segment.mandatory_break)
+            .unwrap_or(true);
+
+        let (mut pen_x, gap) = match style.align {
+            TextAlign::Left => (0., 0.),
+            TextAlign::Center => (free_space / 2., 0.),
+            TextAlign::Right => (free_space, 0.),
+            // The last line of a paragraph is left aligned.
+            TextAlign::Justify if ends_paragraph || line_segments.len() < 2 => (0., 0.),
+            TextAlign::Justify => (0., free_space / (line_segments.len() - 1) as f32),
+        };
+        let y = baseline - line_index as f32 * line_height;
+
+        for segment in line_segments {
+            for shaped in &glyphs[segment.glyphs.clone()] {
+                if is_control(text, shaped) {
+                    continue;
+                }
+
+                positioned.push(PositionedGlyph {
+                    glyph: shaped.glyph,
+                    face: shaped.face,
+                    cluster: shaped.cluster,
+                    x: pen_x + shaped.x_offset * scale,
+                    y: y + shaped.y_offset * scale,
+                    advance: shaped.x_advance * scale,
+                });
+                pen_x += shaped.x_advance * scale;
+            }
+            pen_x += gap;
+        }
+    }
+
+    TextLayout {
+        glyphs: positioned,
+        width,
+        num_lines: lines.len(),
+        line_height,
+        baseline,
+    }
+}
+
+/// The first character a glyph represents.
+fn first_char(text: &str, shaped: &ShapedGlyph) -> Option<char> {
+    text[shaped.cluster..].chars().next()
+}
+
+/// Whether a glyph represents a control character like a newline, which
+/// takes up no space and isn't drawn.
+fn is_control(text: &str, shaped: &ShapedGlyph) -> bool {
+    matches!(
+        first_char(text, shaped),
+        Some(ch) if ch.is_control() || ch == '\u{2028}' || ch == '\u{2029}'
+    )
+}
+
+fn is_whitespace(text: &str, shaped: &ShapedGlyph) -> bool {
+    matches!(first_char(text, shaped), Some(ch) if ch.is_whitespace())
+}
+
+/// Splits shaped text at its line break opportunities.
+fn segments(text: &str, glyphs: &[ShapedGlyph], scale: f32) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+
+    for (offset, opportunity) in linebreaks(text) {
+        let mut end = start;
+        while end < glyphs.len() && glyphs[end].cluster < offset {
+            end += 1;
+        }
+
+        let mandatory_break = opportunity == BreakOpportunity::Mandatory;
+        // Opportunities inside a run of right-to-left text, whose glyphs are in
+        // visual order, leave nothing to break.
+        if start == end && !mandatory_break {
+            continue;
+        }
+
+        let mut segment = Segment {
+            glyphs: start..end,
+            width: 0.,
+            trailing_whitespace: 0.,
+            mandatory_break,
+        };
+        for shaped in &glyphs[start..end] {
+            let advance = if is_control(text, shaped) {
+                0.
+            } else {
+                shaped.x_advance * scale
+            };
+
+            segment.width += advance;
+            if is_whitespace(text, shaped) {
+                segment.trailing_whitespace += advance;
+            } else {
+                segment.trailing_whitespace = 0.;
+            }
+        }
+
+        segments.push(segment);
+        start = end;
+    }
+
+    segments
+}
+
+/// Greedily fills lines with as many segments as fit in `max_width`. A segment
+/// that doesn't fit on a line of its own overflows it.
+fn break_lines(segments: &[Segment], max_width: Option<f32>) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut width = 0.;
+
+    for (i, segment) in segments.iter().enumerate() {
+        let fits = match max_width {
+            Some(max_width) => width + segment.width - segment.trailing_whitespace <= max_width,
+            None => true,
+        };
+
+        if !fits && i > start {
+            lines.push(Line {
+                segments: start..i,
+                width: width - segments[i - 1].trailing_whitespace,
+            });
+            start = i;
+            width = 0.;
+        }
+
+        width += segment.width;
+
+        if segment.mandatory_break {
+            lines.push(Line {
+                segments: start..i + 1,
+                width: width - segment.trailing_whitespace,
+            });
+            start = i + 1;
+            width = 0.;
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Shapes every character into a glyph 10 pixels wide, with the character
+    /// code as its ID.
+    fn shape(text: &str) -> Vec<ShapedGlyph> {
+        text.char_indices()
+            .map(|(cluster, ch)| ShapedGlyph {
+                glyph: ch as GlyphId,
+                face: 0,
+                cluster,
+                x_offset: 0.,
+                y_offset: 0.,
+                x_advance: 10.,
+            })
+            .collect()
+    }
+
+    fn lay_out(text: &str, style: &TextStyle) -> TextLayout {
+        let metrics = FontMetrics {
+            ascent: 16.,
+            descent: 4.,
+            line_gap: 4.,
+        };
+
+        layout_text(text, &shape(text), 1., &metrics, style)
+    }
+
+    /// The position of the first glyph of each line.
+    fn line_starts(layout: &TextLayout) -> Vec<(char, f32, f32)> {
+        let mut starts: Vec<(char, f32, f32)> = Vec::new();
+        for glyph in &layout.glyphs {
+            if starts.last().map(|&(_, _, y)| y) != Some(glyph.y) {
+                starts.push((std::char::from_u32(glyph.glyph).unwrap(), glyph.x, glyph.y));
+            }
+        }
+
+        starts
+    }
+
+    #[test]
+    fn single_line() {
+        let layout = lay_out("ab c", &TextStyle::default());
+
+        assert_eq!(1, layout.num_lines);
+        assert_eq!(40., layout.width);
+        assert_eq!(
+            vec![0., 10., 20., 30.],
+            layout.glyphs.iter().map(|g| g.x).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn newlines_break_lines_and_are_not_drawn() {
+        let style = TextStyle::new().line_height(1.5);
+        let layout = lay_out("ab\ncd", &style);
+
+        assert_eq!(4, layout.glyphs.len());
+        assert_eq!(vec![('a', 0., 0.), ('c', 0., -36.)], line_starts(&layout));
+    }
+
+    #[test]
+    fn text_wraps_at_max_width() {
+        let style = TextStyle::new().max_width(55.);
+        let layout = lay_out("aa bb cc", &style);
+
+        // The space after a word doesn't count towards the width of its line.
+        assert_eq!(vec![('a', 0., 0.), ('c', 0., -24.)], line_starts(&layout));
+    }
+
+    #[test]
+    fn long_words_overflow() {
+        let style = TextStyle::new().max_width(25.);
+        let layout = lay_out("aaaa b", &style);
+
+        assert_eq!(vec![('a', 0., 0.), ('b', 0., -24.)], line_starts(&layout));
+    }
+
+    #[test]
+    fn right_to_left_runs_are_not_wrapped() {
+        // Right-to-left text is shaped in visual order, with the last cluster
+        // first.
+        let text = "aa bb";
+        let mut glyphs = shape(text);
+        glyphs.reverse();
+        let metrics = FontMetrics {
+            ascent: 16.,
+            descent: 4.,
+            line_gap: 4.,
+        };
+
+        let style = TextStyle::new().max_width(25.);
+        let layout = layout_text(text, &glyphs, 1., &metrics, &style);
+
+        assert_eq!(1, layout.num_lines);
+        assert_eq!(5, layout.glyphs.len());
+    }
+
+    #[test]
+    fn lines_are_aligned() {
+        let style = TextStyle::new().max_width(100.).align(TextAlign::Right);
+        let layout = lay_out("aa\nb", &style);
+        assert_eq!(vec![('a', 80., 0.), ('b', 90., -24.)], line_starts(&layout));
+
+        let style = TextStyle::new().max_width(100.).align(TextAlign::Center);
+        let layout = lay_out("aa\nb", &style);
+        assert_eq!(vec![('a', 40., 0.), ('b', 45., -24.)], line_starts(&layout));
+    }
+
+    #[test]
+    fn justified_lines_fill_max_width() {
+        let style = TextStyle::new().max_width(45.).align(TextAlign::Justify);
+        let layout = lay_out("a b c d", &style);
+
+        // "a b " is 30 pixels wide without its trailing space, so the gap between
+        // its words grows by 15. The last line is left aligned.
+        let xs: Vec<f32> = layout.glyphs.iter().map(|g| g.x).collect();
+        assert_eq!(vec![0., 10., 35., 45., 0., 10., 20.], xs);
+    }
+
+    #[test]
+    fn text_is_anchored_by_baseline() {
+        let starts = |baseline| {
+            let style = TextStyle::new().baseline(baseline);
+            line_starts(&lay_out("a\nb", &style))
+        };
+
+        // Lines are 24 pixels apart, with 16 pixels above and 4 below each
+        // baseline.
+        assert_eq!(
+            vec![('a', 0., 0.), ('b', 0., -24.)],
+            starts(TextBaseline::Alphabetic)
+        );
+        assert_eq!(
+            vec![('a', 0., -16.), ('b', 0., -40.)],
+            starts(TextBaseline::Top)
+        );
+        assert_eq!(
+            vec![('a', 0., 28.), ('b', 0., 4.)],
+            starts(TextBaseline::Bottom)
+        );
+        assert_eq!(
+            vec![('a', 0., 6.), ('b', 0., -18.)],
+            starts(TextBaseline::Middle)
+        );
+    }
+}
@@ -1,17 +1,50 @@
-use wasm_bindgen::JsValue;
-use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    WebGl2RenderingContext, WebGlBuffer, WebGlProgram, WebGlTexture, WebGlVertexArrayObject,
+};
 
+pub use crate::canvas_rasterizer::CanvasRasterizer;
+use crate::dom::get_window;
 use crate::error::GlyphAtlasError;
-pub use crate::font::Font;
-use crate::glyph_atlas::GlyphAtlas;
+pub use crate::font::{Font, FontStretch, FontStyle, FontVariant};
+pub use crate::font_registry::FontRegistry;
+use crate::glyph_atlas::{DirtyRect, GlyphAtlas};
+pub use crate::layout::{GlyphExtents, TextExtents};
+#[cfg(feature = "native")]
+pub use crate::native_rasterizer::NativeRasterizer;
+use crate::options::valid_pixel_ratio;
+pub use crate::options::{
+    AtlasOptions, BlendMode, GlyphMode, QuadMode, RendererOptions, TextureFilter, YAxis,
+};
+pub use crate::pipeline::TextHandle;
+use crate::pipeline::TextPipeline;
+use crate::quad::{quad_indices, BlitQuad, BlitVertex};
+pub use crate::rasterizer::{
+    FontMetrics, GlyphBitmap, GlyphId, GlyphRasterizer, GlyphShape, ShapedGlyph,
+};
 use crate::shader::{compile_shader, link_program};
+pub use crate::style::{TextAlign, TextBaseline, TextStyle};
+use crate::transform::{multiply, pixel_projection};
+pub use crate::transform::{Affine, Matrix, IDENTITY};
 
+mod canvas_rasterizer;
 mod dom;
 mod error;
 mod font;
+mod font_registry;
 mod glyph_atlas;
+mod layout;
+#[cfg(feature = "native")]
+mod native_rasterizer;
+mod options;
 mod packing;
+mod pipeline;
+mod quad;
+mod rasterizer;
+mod sdf;
 pub mod shader;
+mod style;
+mod transform;
 
 #[allow(unused)]
 macro_rules! console_log {
@@ -28,25 +61,89 @@ pub fn log_error(error: GlyphAtlasError) {
     )));
 }
 
+/// An implementation-dependent limit of the context, such as `MAX_TEXTURE_SIZE`.
+fn gl_limit(gl: &WebGl2RenderingContext, parameter: u32) -> Result<u32, GlyphAtlasError> {
+    gl.get_parameter(parameter)
+        .ok()
+        .and_then(|value| value.as_f64())
+        .map(|value| value as u32)
+        .ok_or_else(|| GlyphAtlasError::WebGlError("Could not query context limits.".to_string()))
+}
+
+/// The vertex array object bound to the context, if any, so that it can be
+/// restored after drawing with our own.
+fn bound_vertex_array(gl: &WebGl2RenderingContext) -> Option<WebGlVertexArrayObject> {
+    gl.get_parameter(WebGl2RenderingContext::VERTEX_ARRAY_BINDING)
+        .ok()?
+        .dyn_into::<WebGlVertexArrayObject>()
+        .ok()
+}
+
 pub struct Renderer<'a> {
     gl: &'a WebGl2RenderingContext,
     program: WebGlProgram,
-    atlas: GlyphAtlas,
-    queued_text: Vec<(String, Font, f32, f32)>, // TODO: Use FontIndex, not Font
+    pipeline: TextPipeline,
     texture: WebGlTexture,
+    texture_filter: TextureFilter,
+    sdf: bool,
+    /// Size of the atlas pages when the atlas was last uploaded to `texture`.
+    texture_size: (u32, u32),
+    /// Number of atlas pages (texture array layers) allocated in `texture`.
+    texture_pages: usize,
     buffer: WebGlBuffer,
-    quads: Vec<BlitQuad>,
+    /// Holds the attribute layout of `buffer` (and `index_buffer`), so that the
+    /// attribute state of the context, which may be shared, is left alone.
+    vertex_array: WebGlVertexArrayObject,
+    /// Number of quads there is room for in `buffer`.
+    buffer_capacity: usize,
+    quad_mode: QuadMode,
+    /// Indices of the vertices of each quad, in `QuadMode::Indexed`.
+    index_buffer: WebGlBuffer,
+    /// Number of quads `index_buffer` has indices for.
+    index_capacity: usize,
+    font_registry: Option<FontRegistry>,
+    /// Number of fonts of `font_registry` known to have loaded.
+    loaded_fonts: usize,
+    y_axis: YAxis,
+    /// Applied to pixel coordinates before they are projected to clip space.
+    transform: Matrix,
+    /// Replaces the projection of pixel coordinates to clip space, if set.
+    projection: Option<Matrix>,
 }
 
 impl<'a> Renderer<'a> {
     pub fn new(gl: &WebGl2RenderingContext) -> Result<Renderer, GlyphAtlasError> {
+        Renderer::with_options(gl, &RendererOptions::default())
+    }
+
+    /// Creates a renderer which draws glyphs with a hidden canvas element.
+    ///
+    /// Unless [`RendererOptions::pixel_ratio`] says otherwise, glyphs are
+    /// rasterized at the window's `devicePixelRatio`, so on a high-DPI display
+    /// the canvas's drawing buffer should be that many times its CSS size, or
+    /// text will be drawn enlarged.
+    pub fn with_options(
+        gl: &'a WebGl2RenderingContext,
+        options: &RendererOptions,
+    ) -> Result<Renderer<'a>, GlyphAtlasError> {
+        Renderer::with_rasterizer(gl, options, Box::new(CanvasRasterizer::new()?))
+    }
+
+    /// Creates a renderer which draws glyphs using the given rasterizer instead of
+    /// a canvas element.
+    pub fn with_rasterizer(
+        gl: &'a WebGl2RenderingContext,
+        options: &RendererOptions,
+        rasterizer: Box<dyn GlyphRasterizer>,
+    ) -> Result<Renderer<'a>, GlyphAtlasError> {
         gl.enable(WebGl2RenderingContext::BLEND);
+        options.blend_mode.apply(gl);
 
-        let vert_shader = compile_shader(
-            gl,
-            WebGl2RenderingContext::VERTEX_SHADER,
-            include_str!("shader.vert"),
-        )?;
+        let vert_source = match options.quad_mode {
+            QuadMode::Instanced => include_str!("shader.vert"),
+            QuadMode::Indexed => include_str!("shader_indexed.vert"),
+        };
+        let vert_shader = compile_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, vert_source)?;
 
         let frag_shader = compile_shader(
             gl,
@@ -55,235 +152,488 @@ impl<'a> Renderer<'a> {
         )?;
 
         let program = link_program(gl, &vert_shader, &frag_shader)?;
-        let atlas = GlyphAtlas::new()?;
+        // Larger textures would only fail once the atlas grows into them.
+        let atlas_options = options.atlas.clamped_to(
+            gl_limit(gl, WebGl2RenderingContext::MAX_TEXTURE_SIZE)?,
+            gl_limit(gl, WebGl2RenderingContext::MAX_ARRAY_TEXTURE_LAYERS)?,
+        );
+        let atlas = GlyphAtlas::new(&atlas_options, rasterizer);
         let texture = gl.create_texture().ok_or_else(|| {
             GlyphAtlasError::WebGlError("Could not allocate texture.".to_string())
         })?;
 
-        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&texture));
-        gl.tex_image_2d_with_u32_and_u32_and_image_data(
-            WebGl2RenderingContext::TEXTURE_2D,    // target
-            0,                                     // level
-            WebGl2RenderingContext::RGBA as i32,   // internalformat
-            WebGl2RenderingContext::RGBA,          // format
-            WebGl2RenderingContext::UNSIGNED_BYTE, // type
-            &atlas.image_data()?,                  // data
-        )
-        .map_err(|_| GlyphAtlasError::WebGlError("Could not write to texture.".to_string()))?;
-
         let buffer = gl.create_buffer().ok_or_else(|| {
             GlyphAtlasError::WebGlError("Could not create vertex buffer.".to_string())
         })?;
-        let quads = Vec::new();
+        let index_buffer = gl.create_buffer().ok_or_else(|| {
+            GlyphAtlasError::WebGlError("Could not create index buffer.".to_string())
+        })?;
 
-        Ok(Renderer {
+        let vertex_array = gl.create_vertex_array().ok_or_else(|| {
+            GlyphAtlasError::WebGlError("Could not create vertex array.".to_string())
+        })?;
+        let previous_vertex_array = bound_vertex_array(gl);
+        gl.bind_vertex_array(Some(&vertex_array));
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&buffer));
+        match options.quad_mode {
+            QuadMode::Instanced => BlitQuad::describe(gl, &program),
+            QuadMode::Indexed => {
+                BlitVertex::describe(gl, &program);
+                gl.bind_buffer(
+                    WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                    Some(&index_buffer),
+                );
+            }
+        }
+        gl.bind_vertex_array(previous_vertex_array.as_ref());
+
+        let sdf = matches!(
+            options.atlas.glyph_mode,
+            GlyphMode::SignedDistanceField { .. }
+        );
+        // Distance fields are scaled, so they always need to be interpolated.
+        let texture_filter = if sdf {
+            TextureFilter::Linear
+        } else {
+            options.texture_filter
+        };
+
+        let mut pipeline = TextPipeline::new(atlas);
+        pipeline.set_y_axis(options.y_axis);
+        pipeline.set_scale(options.pixel_ratio.unwrap_or_else(|| {
+            get_window()
+                .ok()
+                .and_then(|window| valid_pixel_ratio(window.device_pixel_ratio() as f32))
+                .unwrap_or(1.)
+        }));
+
+        let mut renderer = Renderer {
             gl,
             program,
-            atlas,
-            queued_text: Vec::new(),
+            pipeline,
             texture,
+            texture_filter,
+            sdf,
+            texture_size: (0, 0),
+            texture_pages: 0,
             buffer,
-            quads,
+            vertex_array,
+            buffer_capacity: 0,
+            quad_mode: options.quad_mode,
+            index_buffer,
+            index_capacity: 0,
+            font_registry: None,
+            loaded_fonts: 0,
+            y_axis: options.y_axis,
+            transform: IDENTITY,
+            projection: None,
+        };
+        renderer.bind_texture();
+        renderer.allocate_texture()?;
+
+        Ok(renderer)
+    }
+
+    /// (Re-)allocates the texture array at the current size and page count of the
+    /// atlas and uploads every page to it. Assumes the texture is bound.
+    fn allocate_texture(&mut self) -> Result<(), GlyphAtlasError> {
+        let (width, height) = self.pipeline.atlas().size();
+        let pages = self.pipeline.atlas().num_pages();
+
+        self.gl
+            .tex_image_3d_with_opt_u8_array(
+                WebGl2RenderingContext::TEXTURE_2D_ARRAY, // target
+                0,                                        // level
+                WebGl2RenderingContext::RGBA as i32,      // internalformat
+                width as i32,                             // width
+                height as i32,                            // height
+                pages as i32,                             // depth
+                0,                                        // border
+                WebGl2RenderingContext::RGBA,             // format
+                WebGl2RenderingContext::UNSIGNED_BYTE,    // type
+                None,                                     // data
+            )
+            .map_err(|_| GlyphAtlasError::WebGlError("Could not write to texture.".to_string()))?;
+        self.texture_size = (width, height);
+        self.texture_pages = pages;
+
+        for page in 0..pages {
+            self.upload_page(page)?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies one page of the atlas into its layer of the texture array. Assumes the
+    /// texture is bound.
+    fn upload_page(&self, page: usize) -> Result<(), GlyphAtlasError> {
+        let (width, height) = self.texture_size;
+
+        self.upload_region(&DirtyRect {
+            page,
+            x: 0,
+            y: 0,
+            width,
+            height,
         })
     }
 
-    fn bind_texture(&mut self) {
+    /// Copies a modified region of an atlas page into the texture array. Assumes the
+    /// texture is bound.
+    fn upload_region(&self, rect: &DirtyRect) -> Result<(), GlyphAtlasError> {
+        let (page_width, _) = self.texture_size;
+        let pixels = self.pipeline.atlas().pixels(rect.page);
+        let offset = ((rect.y * page_width + rect.x) * 4) as usize;
+
+        // Read rows at the stride of the full page, starting from the first pixel
+        // of the rect, so that we don't have to copy the rect out of the page.
         self.gl
-            .bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(&self.texture));
+            .pixel_storei(WebGl2RenderingContext::UNPACK_ROW_LENGTH, page_width as i32);
+
+        let result = self
+            .gl
+            .tex_sub_image_3d_with_opt_u8_array(
+                WebGl2RenderingContext::TEXTURE_2D_ARRAY, // target
+                0,                                        // level
+                rect.x as i32,                            // xoffset
+                rect.y as i32,                            // yoffset
+                rect.page as i32,                         // zoffset
+                rect.width as i32,                        // width
+                rect.height as i32,                       // height
+                1,                                        // depth
+                WebGl2RenderingContext::RGBA,             // format
+                WebGl2RenderingContext::UNSIGNED_BYTE,    // type
+                Some(&pixels[offset..]),                  // data
+            )
+            .map_err(|_| GlyphAtlasError::WebGlError("Could not update texture.".to_string()));
+
+        self.gl
+            .pixel_storei(WebGl2RenderingContext::UNPACK_ROW_LENGTH, 0);
+
+        result
+    }
+
+    fn bind_texture(&mut self) {
+        self.gl.bind_texture(
+            WebGl2RenderingContext::TEXTURE_2D_ARRAY,
+            Some(&self.texture),
+        );
 
         self.gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_2D_ARRAY,
             WebGl2RenderingContext::TEXTURE_WRAP_S,
             WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
         );
         self.gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_2D_ARRAY,
             WebGl2RenderingContext::TEXTURE_WRAP_T,
             WebGl2RenderingContext::CLAMP_TO_EDGE as i32,
         );
         self.gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_2D_ARRAY,
             WebGl2RenderingContext::TEXTURE_MIN_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
+            self.texture_filter.gl_enum(),
         );
         self.gl.tex_parameteri(
-            WebGl2RenderingContext::TEXTURE_2D,
+            WebGl2RenderingContext::TEXTURE_2D_ARRAY,
             WebGl2RenderingContext::TEXTURE_MAG_FILTER,
-            WebGl2RenderingContext::NEAREST as i32,
+            self.texture_filter.gl_enum(),
         );
     }
 
+    /// Changes the number of device pixels per logical pixel, e.g. when the window
+    /// moves to a display with a different `devicePixelRatio`. Ignored unless
+    /// positive. See `RendererOptions::pixel_ratio`.
+    pub fn set_pixel_ratio(&mut self, pixel_ratio: f32) {
+        if let Some(pixel_ratio) = valid_pixel_ratio(pixel_ratio) {
+            self.pipeline.set_scale(pixel_ratio);
+        }
+    }
+
+    pub fn pixel_ratio(&self) -> f32 {
+        self.pipeline.atlas().scale()
+    }
+
+    /// Changes the direction of the y axis of the pixel coordinates text is
+    /// positioned in. See `RendererOptions::y_axis`.
+    pub fn set_y_axis(&mut self, y_axis: YAxis) {
+        self.y_axis = y_axis;
+        self.pipeline.set_y_axis(y_axis);
+    }
+
+    /// Sets a matrix (in column-major order) that is applied to the pixel
+    /// coordinates of text when it is drawn, e.g. to pan, zoom or rotate it.
+    /// Glyphs are still rasterized at their unscaled size.
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.transform = transform;
+    }
+
+    pub fn transform(&self) -> Matrix {
+        self.transform
+    }
+
+    /// Sets a matrix (in column-major order) that maps pixel coordinates to clip
+    /// space, instead of mapping the drawing buffer to the viewport. `None`
+    /// restores the default.
+    pub fn set_projection(&mut self, projection: Option<Matrix>) {
+        self.projection = projection;
+    }
+
+    /// Watches a registry for fonts that finish loading, and redraws any glyphs
+    /// of their families that were drawn with a fallback font in the meantime.
+    pub fn use_font_registry(&mut self, registry: &FontRegistry) {
+        self.font_registry = Some(registry.clone());
+        // Glyphs may have been drawn before fonts that already loaded.
+        self.loaded_fonts = 0;
+    }
+
+    /// Invalidates the glyphs of families whose fonts loaded since the last call.
+    fn update_fonts(&mut self) {
+        let registry = match &self.font_registry {
+            Some(registry) => registry,
+            None => return,
+        };
+
+        let families = registry.loaded_families(self.loaded_fonts);
+        self.loaded_fonts += families.len();
+        for family in families {
+            self.pipeline.invalidate_family(&family);
+        }
+    }
+
     pub fn queue_text(&mut self, text: &str, font: &Font, x: f32, y: f32) {
-        self.queued_text
-            .push((text.to_string(), font.clone(), x, y));
+        self.queue_text_with_style(text, font, x, y, &TextStyle::default());
     }
 
-    pub fn draw(&mut self) -> Result<(), GlyphAtlasError> {
-        self.quads.clear();
-        let width = self.gl.drawing_buffer_width() as f32;
-        let height = self.gl.drawing_buffer_height() as f32;
+    pub fn queue_text_with_style(
+        &mut self,
+        text: &str,
+        font: &Font,
+        x: f32,
+        y: f32,
+        style: &TextStyle,
+    ) {
+        self.pipeline.queue_text(text, font, x, y, style);
+    }
 
-        self.gl.use_program(Some(&self.program));
-        self.bind_texture();
+    /// Adds text that is drawn by every call to `draw` until it is removed, in
+    /// the order it was added and below any queued text. Unlike queued text, it is
+    /// only laid out again, and its vertices only uploaded again, when it changes.
+    pub fn add_text(
+        &mut self,
+        text: &str,
+        font: &Font,
+        x: f32,
+        y: f32,
+        style: &TextStyle,
+    ) -> TextHandle {
+        self.pipeline.add_text(text, font, x, y, style)
+    }
 
-        let need_to_update_texture = self.atlas.prepare_text(
-            self.queued_text
-                .iter()
-                .map(|(text, font, _, _)| (text.as_str(), font))
-                .collect(),
-        );
+    pub fn set_text(&mut self, handle: TextHandle, text: &str) {
+        self.pipeline.set_text(handle, text);
+    }
+
+    pub fn set_text_font(&mut self, handle: TextHandle, font: &Font) {
+        self.pipeline.set_text_font(handle, font);
+    }
+
+    pub fn set_text_style(&mut self, handle: TextHandle, style: &TextStyle) {
+        self.pipeline.set_text_style(handle, style);
+    }
+
+    /// Moves text, which is cheaper than changing anything else about it.
+    pub fn set_text_position(&mut self, handle: TextHandle, x: f32, y: f32) {
+        self.pipeline.set_text_position(handle, x, y);
+    }
+
+    pub fn remove_text(&mut self, handle: TextHandle) {
+        self.pipeline.remove_text(handle);
+    }
+
+    /// Measures text as it would be drawn by `queue_text`, without drawing it.
+    pub fn measure_text(
+        &mut self,
+        text: &str,
+        font: &Font,
+    ) -> Result<TextExtents, GlyphAtlasError> {
+        self.measure_text_with_style(text, font, &TextStyle::default())
+    }
 
-        if need_to_update_texture? {
-            self.gl
-                .tex_sub_image_2d_with_u32_and_u32_and_image_data(
-                    WebGl2RenderingContext::TEXTURE_2D,    // target
-                    0,                                     // level
-                    0,                                     // xoffset
-                    0,                                     // yoffset
-                    WebGl2RenderingContext::RGBA,          // format
-                    WebGl2RenderingContext::UNSIGNED_BYTE, // type
-                    &self.atlas.image_data()?,             // data
-                )
-                .map_err(|_| GlyphAtlasError::WebGlError("Could not update texture.".to_string()))?
+    pub fn measure_text_with_style(
+        &mut self,
+        text: &str,
+        font: &Font,
+        style: &TextStyle,
+    ) -> Result<TextExtents, GlyphAtlasError> {
+        self.update_fonts();
+        self.pipeline.measure_text(text, font, style)
+    }
+
+    /// The matrix that maps pixel coordinates to clip space.
+    fn projection(&self) -> Matrix {
+        self.projection.unwrap_or_else(|| {
+            // Text is positioned in logical pixels.
+            let scale = self.pixel_ratio();
+            pixel_projection(
+                self.gl.drawing_buffer_width() as f32 / scale,
+                self.gl.drawing_buffer_height() as f32 / scale,
+                self.y_axis,
+            )
+        })
+    }
+
+    /// Number of bytes each quad takes up in the vertex buffer.
+    fn quad_bytes(&self) -> usize {
+        match self.quad_mode {
+            QuadMode::Instanced => std::mem::size_of::<BlitQuad>(),
+            QuadMode::Indexed => 4 * std::mem::size_of::<BlitVertex>(),
         }
+    }
 
-        let x_scale = 2. / width;
-        let y_scale = 2. / height;
-        let x_offset = -1.;
-        let y_offset = -1.;
-
-        for (text, font, x, y) in self.queued_text.drain(..) {
-            let chars: Vec<char> = text.chars().collect();
-            let mut x = x;
-
-            for ch in chars {
-                let entry = self.atlas.get_entry(ch, &font)?;
-
-                let (tex_upper_left, tex_lower_right) = entry.texture_scaled_bounds();
-
-                let glyph_width = entry.glyph_shape.glyph_width();
-                let glyph_height = entry.glyph_shape.height();
-                let glyph_offset = entry.glyph_shape.descent;
-
-                let blit_upper_left = [
-                    (x.round() * x_scale) + x_offset,
-                    ((y - glyph_offset as f32 + glyph_height as f32) * y_scale) + y_offset,
-                ];
-                let blit_lower_right = [
-                    ((x.round() + glyph_width as f32) * x_scale) + x_offset,
-                    ((y - glyph_offset as f32) * y_scale) + y_offset,
-                ];
-
-                self.quads.push(BlitQuad::new(
-                    blit_lower_right,
-                    blit_upper_left,
-                    tex_lower_right,
-                    tex_upper_left,
-                ));
-
-                x += entry.glyph_shape.occupied_width;
-            }
+    /// Copies a range of the quads into the vertex buffer. Assumes the buffer is
+    /// bound and large enough.
+    fn upload_quads(&self, range: std::ops::Range<usize>) {
+        if range.is_empty() {
+            return;
         }
 
-        self.gl
-            .bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffer));
+        let offset = range.start * self.quad_bytes();
+        let quads = &self.pipeline.quads()[range];
+        let vertices: Vec<BlitVertex>;
+        let floats: &[f32] = match self.quad_mode {
+            QuadMode::Instanced => bytemuck::cast_slice(quads),
+            QuadMode::Indexed => {
+                vertices = quads.iter().flat_map(BlitQuad::vertices).collect();
+                bytemuck::cast_slice(&vertices)
+            }
+        };
 
         unsafe {
-            let vert_array = js_sys::Float32Array::view(&bytemuck::cast_slice(&self.quads));
+            let vert_array = js_sys::Float32Array::view(floats);
 
-            self.gl.buffer_data_with_array_buffer_view(
+            self.gl.buffer_sub_data_with_i32_and_array_buffer_view(
                 WebGl2RenderingContext::ARRAY_BUFFER,
+                offset as i32,
                 &vert_array,
-                WebGl2RenderingContext::DYNAMIC_DRAW,
             );
         }
+    }
 
-        BlitVertex::describe(self.gl, &self.program);
+    /// Type of the indices in `index_buffer`: 16 bits while they fit.
+    fn index_type(&self) -> u32 {
+        if self.index_capacity * 4 <= 1 << 16 {
+            WebGl2RenderingContext::UNSIGNED_SHORT
+        } else {
+            WebGl2RenderingContext::UNSIGNED_INT
+        }
+    }
 
-        self.gl.draw_arrays(
-            WebGl2RenderingContext::TRIANGLES,
-            0,
-            (self.quads.len() * 6) as i32,
+    /// Binds the index buffer, growing it if it doesn't have indices for the
+    /// given number of quads.
+    fn bind_indices(&mut self, num_quads: usize) {
+        self.gl.bind_buffer(
+            WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+            Some(&self.index_buffer),
         );
+        if num_quads <= self.index_capacity {
+            return;
+        }
 
-        Ok(())
+        self.index_capacity = num_quads.next_power_of_two();
+        let indices = quad_indices(self.index_capacity);
+        if self.index_type() == WebGl2RenderingContext::UNSIGNED_SHORT {
+            let indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+            unsafe {
+                self.gl.buffer_data_with_array_buffer_view(
+                    WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                    &js_sys::Uint16Array::view(&indices),
+                    WebGl2RenderingContext::STATIC_DRAW,
+                );
+            }
+        } else {
+            unsafe {
+                self.gl.buffer_data_with_array_buffer_view(
+                    WebGl2RenderingContext::ELEMENT_ARRAY_BUFFER,
+                    &js_sys::Uint32Array::view(&indices),
+                    WebGl2RenderingContext::STATIC_DRAW,
+                );
+            }
+        }
     }
-}
 
-#[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug)]
-struct BlitQuad {
-    vertices: [BlitVertex; 6],
-}
+    pub fn draw(&mut self) -> Result<(), GlyphAtlasError> {
+        self.gl.use_program(Some(&self.program));
+        self.bind_texture();
+
+        self.update_fonts();
+        let dirty_rects = self.pipeline.prepare()?;
 
-impl BlitQuad {
-    pub fn new(
-        upper_left: [f32; 2],
-        lower_right: [f32; 2],
-        tex_upper_left: [f32; 2],
-        tex_lower_right: [f32; 2],
-    ) -> BlitQuad {
-        let upper_right = [lower_right[0], upper_left[1]];
-        let lower_left = [upper_left[0], lower_right[1]];
-        let tex_upper_right = [tex_lower_right[0], tex_upper_left[1]];
-        let tex_lower_left = [tex_upper_left[0], tex_lower_right[1]];
-
-        BlitQuad {
-            vertices: [
-                BlitVertex {
-                    position: upper_left,
-                    tex_coord: tex_upper_left,
-                },
-                BlitVertex {
-                    position: upper_right,
-                    tex_coord: tex_upper_right,
-                },
-                BlitVertex {
-                    position: lower_left,
-                    tex_coord: tex_lower_left,
-                },
-                BlitVertex {
-                    position: upper_right,
-                    tex_coord: tex_upper_right,
-                },
-                BlitVertex {
-                    position: lower_right,
-                    tex_coord: tex_lower_right,
-                },
-                BlitVertex {
-                    position: lower_left,
-                    tex_coord: tex_lower_left,
-                },
-            ],
+        let atlas = self.pipeline.atlas();
+        if atlas.size() != self.texture_size || atlas.num_pages() != self.texture_pages {
+            // The atlas grew or gained a page, so the texture has to be reallocated.
+            self.allocate_texture()?;
+        } else {
+            for rect in &dirty_rects {
+                self.upload_region(rect)?;
+            }
         }
-    }
-}
 
-#[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug)]
-struct BlitVertex {
-    pub position: [f32; 2],
-    pub tex_coord: [f32; 2],
-}
+        self.gl
+            .bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffer));
 
-impl BlitVertex {
-    fn describe(gl: &WebGl2RenderingContext, program: &WebGlProgram) {
-        let mut offset = 0;
-
-        // Binding is simple because each attribute happens to be a [f32; 2].
-        for attribute in &["a_position", "a_tex_coord"] {
-            let location = gl.get_attrib_location(&program, attribute) as u32;
-            gl.vertex_attrib_pointer_with_i32(
-                location,
-                2,
-                WebGl2RenderingContext::FLOAT,
-                false,
-                std::mem::size_of::<BlitVertex>() as i32,
-                offset,
+        let changed = self.pipeline.take_changed_quads();
+        let num_quads = self.pipeline.quads().len();
+        if num_quads > self.buffer_capacity {
+            // Leave room to grow, so that the buffer isn't reallocated whenever text
+            // is added.
+            self.buffer_capacity = num_quads.next_power_of_two();
+            self.gl.buffer_data_with_i32(
+                WebGl2RenderingContext::ARRAY_BUFFER,
+                (self.buffer_capacity * self.quad_bytes()) as i32,
+                WebGl2RenderingContext::DYNAMIC_DRAW,
             );
-            gl.enable_vertex_attrib_array(location);
+            self.upload_quads(0..num_quads);
+        } else {
+            self.upload_quads(changed);
+        }
+
+        let sdf_location = self.gl.get_uniform_location(&self.program, "u_sdf");
+        self.gl.uniform1i(sdf_location.as_ref(), self.sdf as i32);
 
-            offset += std::mem::size_of::<[f32; 2]>() as i32;
+        let transform_location = self.gl.get_uniform_location(&self.program, "u_transform");
+        self.gl.uniform_matrix4fv_with_f32_array(
+            transform_location.as_ref(),
+            false,
+            &multiply(&self.projection(), &self.transform),
+        );
+
+        let previous_vertex_array = bound_vertex_array(self.gl);
+        self.gl.bind_vertex_array(Some(&self.vertex_array));
+
+        match self.quad_mode {
+            QuadMode::Instanced => {
+                // Each quad is an instance of a four vertex triangle strip.
+                self.gl.draw_arrays_instanced(
+                    WebGl2RenderingContext::TRIANGLE_STRIP,
+                    0,
+                    4,
+                    num_quads as i32,
+                );
+            }
+            QuadMode::Indexed => {
+                self.bind_indices(num_quads);
+                self.gl.draw_elements_with_i32(
+                    WebGl2RenderingContext::TRIANGLES,
+                    (num_quads * 6) as i32,
+                    self.index_type(),
+                    0,
+                );
+            }
         }
+
+        self.gl.bind_vertex_array(previous_vertex_array.as_ref());
+
+        Ok(())
     }
 }
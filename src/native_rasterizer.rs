@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use ab_glyph::{point, Font as _, FontVec, PxScale, ScaleFont};
+use rustybuzz::UnicodeBuffer;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::error::{GlyphAtlasError, Result};
+use crate::rasterizer::{
+    FontMetrics, GlyphBitmap, GlyphId, GlyphRasterizer, GlyphShape, ShapedGlyph,
+};
+use crate::Font;
+
+/// Shapes text with rustybuzz and rasterizes glyphs in pure Rust from
+/// TrueType/OpenType font data, without needing a DOM. Fonts must be registered
+/// with `add_font` under the family name they are referred to by in `Font`.
+/// Only the families and size of a `Font` are used to pick a face; other
+/// properties like weight are ignored.
+///
+/// Each extended grapheme cluster is taken from the first family whose face
+/// maps all of its characters to glyphs, and runs of clusters from the same
+/// face are shaped together.
+#[derive(Default)]
+pub struct NativeRasterizer {
+    faces: HashMap<String, FontVec>,
+}
+
+impl NativeRasterizer {
+    pub fn new() -> NativeRasterizer {
+        NativeRasterizer::default()
+    }
+
+    /// Registers a font from the contents of a TTF or OTF file.
+    pub fn add_font(&mut self, name: &str, data: Vec<u8>) -> Result<()> {
+        let parse_error =
+            || GlyphAtlasError::FontError(format!("Could not parse font data for {}.", name));
+        let face = FontVec::try_from_vec(data).map_err(|_| parse_error())?;
+        // Shaping parses the data again whenever text is shaped, so make sure
+        // that can't fail later.
+        rustybuzz::Face::from_slice(face.as_slice(), 0).ok_or_else(parse_error)?;
+        self.faces.insert(name.to_string(), face);
+
+        Ok(())
+    }
+
+    fn face(&self, font: &Font) -> Result<&FontVec> {
+        self.faces.get(font.name()).ok_or_else(|| {
+            GlyphAtlasError::FontError(format!("Font {} has not been added.", font.name()))
+        })
+    }
+
+    /// The face of a single-family font, parsed for shaping.
+    fn shaping_face(&self, font: &Font) -> Result<rustybuzz::Face<'_>> {
+        let face = self.face(font)?;
+        rustybuzz::Face::from_slice(face.as_slice(), 0).ok_or_else(|| {
+            GlyphAtlasError::FontError(format!("Could not parse font data for {}.", font.name()))
+        })
+    }
+
+    /// Index of the first family of the font with glyphs for every character of
+    /// a cluster, or of the first family if none have them all.
+    fn face_for_cluster(&self, cluster: &str, font: &Font) -> usize {
+        font.families()
+            .iter()
+            .position(|family| match self.faces.get(family) {
+                Some(face) => cluster.chars().all(|ch| face.glyph_id(ch).0 != 0),
+                None => false,
+            })
+            .unwrap_or(0)
+    }
+
+    /// Shapes a run of text that is entirely taken from the family at index
+    /// `face` of the font, which `shaping_face` is the parsed face of. `range` is
+    /// the run's position in the whole text.
+    fn shape_run(
+        &self,
+        text: &str,
+        range: Range<usize>,
+        face: usize,
+        shaping_face: &rustybuzz::Face,
+        font: &Font,
+    ) -> Result<Vec<ShapedGlyph>> {
+        let font = font.face(face);
+        let scale = pixels_per_unit(self.face(&font)?, &font);
+
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&text[range.clone()]);
+        buffer.guess_segment_properties();
+        let output = rustybuzz::shape(shaping_face, &[], buffer);
+
+        Ok(output
+            .glyph_infos()
+            .iter()
+            .zip(output.glyph_positions())
+            .map(|(info, position)| ShapedGlyph {
+                glyph: info.glyph_id,
+                face,
+                cluster: range.start + info.cluster as usize,
+                x_offset: position.x_offset as f32 * scale,
+                y_offset: position.y_offset as f32 * scale,
+                x_advance: position.x_advance as f32 * scale,
+            })
+            .collect())
+    }
+}
+
+/// Pixels per font unit, such that the em square is `font.size()` pixels as in CSS.
+fn pixels_per_unit(face: &FontVec, font: &Font) -> f32 {
+    font.size() / face.units_per_em().unwrap_or(1000.)
+}
+
+/// ab_glyph scales faces by their height rather than their em square.
+fn px_scale(face: &FontVec, font: &Font) -> PxScale {
+    PxScale::from(face.height_unscaled() * pixels_per_unit(face, font))
+}
+
+impl GlyphRasterizer for NativeRasterizer {
+    fn shape_text(&mut self, text: &str, font: &Font) -> Result<Vec<ShapedGlyph>> {
+        // Runs of consecutive clusters taken from the same family.
+        let mut runs: Vec<(usize, Range<usize>)> = Vec::new();
+        for (offset, cluster) in text.grapheme_indices(true) {
+            let face = self.face_for_cluster(cluster, font);
+            let end = offset + cluster.len();
+
+            match runs.last_mut() {
+                Some((run_face, range)) if *run_face == face => range.end = end,
+                _ => runs.push((face, offset..end)),
+            }
+        }
+
+        // Each face is parsed for shaping at most once per text.
+        let mut shaping_faces: Vec<Option<rustybuzz::Face>> =
+            font.families().iter().map(|_| None).collect();
+        let mut glyphs = Vec::with_capacity(text.len());
+        for (face, range) in runs {
+            if shaping_faces[face].is_none() {
+                shaping_faces[face] = Some(self.shaping_face(&font.face(face))?);
+            }
+            let shaping_face = shaping_faces[face].as_ref().unwrap();
+            glyphs.extend(self.shape_run(text, range, face, shaping_face, font)?);
+        }
+
+        Ok(glyphs)
+    }
+
+    fn font_metrics(&mut self, font: &Font) -> Result<FontMetrics> {
+        let face = self.face(font)?;
+        let scale = pixels_per_unit(face, font);
+
+        Ok(FontMetrics {
+            ascent: face.ascent_unscaled() * scale,
+            descent: -face.descent_unscaled() * scale,
+            line_gap: face.line_gap_unscaled() * scale,
+        })
+    }
+
+    fn measure_glyph(&mut self, glyph: GlyphId, font: &Font) -> Result<GlyphShape> {
+        let face = self.face(font)?;
+        let scale = px_scale(face, font);
+        let glyph_id = ab_glyph::GlyphId(glyph as u16);
+        let advance = face.as_scaled(scale).h_advance(glyph_id);
+
+        let outline = face.outline_glyph(glyph_id.with_scale_and_position(scale, point(0., 0.)));
+        let shape = match outline {
+            Some(outline) => {
+                let bounds = outline.px_bounds();
+
+                GlyphShape {
+                    left: (-bounds.min.x).max(0.) as u32,
+                    right: bounds.max.x.max(0.) as u32,
+                    ascent: (-bounds.min.y).max(0.) as u32,
+                    descent: bounds.max.y.max(0.) as u32,
+                    occupied_width: advance,
+                }
+            }
+            // Glyphs without an outline, like spaces, only take up space.
+            None => GlyphShape {
+                left: 0,
+                right: 0,
+                ascent: 0,
+                descent: 0,
+                occupied_width: advance,
+            },
+        };
+
+        Ok(shape)
+    }
+
+    fn rasterize_glyph(
+        &mut self,
+        glyph: GlyphId,
+        font: &Font,
+        shape: &GlyphShape,
+        offset: [f32; 2],
+    ) -> Result<GlyphBitmap> {
+        let face = self.face(font)?;
+        let scale = px_scale(face, font);
+        let glyph = ab_glyph::GlyphId(glyph as u16)
+            .with_scale_and_position(scale, point(offset[0], offset[1]));
+
+        let mut bitmap = GlyphBitmap::new(shape.glyph_width(), shape.height());
+
+        if let Some(outline) = face.outline_glyph(glyph) {
+            let bounds = outline.px_bounds();
+            // Position of the outline's upper left corner within the bitmap.
+            let offset_x = shape.left as i32 + bounds.min.x as i32;
+            let offset_y = shape.ascent as i32 + bounds.min.y as i32;
+
+            outline.draw(|x, y, coverage| {
+                let x = offset_x + x as i32;
+                let y = offset_y + y as i32;
+
+                if x < 0 || y < 0 || x >= bitmap.width as i32 || y >= bitmap.height as i32 {
+                    return;
+                }
+
+                let index = ((y as u32 * bitmap.width + x as u32) * 4) as usize;
+                let alpha = (coverage.min(1.) * 255.).round() as u8;
+                bitmap.data[index..index + 4].copy_from_slice(&[255, 255, 255, alpha]);
+            });
+        }
+
+        Ok(bitmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACIFICO: &[u8] = include_bytes!("../demos/font/Pacifico-Regular.ttf");
+
+    fn rasterizer() -> NativeRasterizer {
+        let mut rasterizer = NativeRasterizer::new();
+        rasterizer.add_font("Pacifico", PACIFICO.to_vec()).unwrap();
+        rasterizer
+    }
+
+    fn glyph(ch: char) -> GlyphId {
+        let face = FontVec::try_from_vec(PACIFICO.to_vec()).unwrap();
+        face.glyph_id(ch).0 as GlyphId
+    }
+
+    #[test]
+    fn glyphs_are_scaled_to_the_em_square() {
+        let face = FontVec::try_from_vec(PACIFICO.to_vec()).unwrap();
+        let units_per_em = face.units_per_em().unwrap();
+        let advance = face.h_advance_unscaled(face.glyph_id('a'));
+
+        let shape = rasterizer()
+            .measure_glyph(glyph('a'), &Font::new("Pacifico", 100.))
+            .unwrap();
+
+        assert!((advance * 100. / units_per_em - shape.occupied_width).abs() < 0.01);
+    }
+
+    #[test]
+    fn metrics_are_scaled_to_the_em_square() {
+        let face = FontVec::try_from_vec(PACIFICO.to_vec()).unwrap();
+        let scale = 100. / face.units_per_em().unwrap();
+
+        let metrics = rasterizer()
+            .font_metrics(&Font::new("Pacifico", 100.))
+            .unwrap();
+
+        assert!((face.ascent_unscaled() * scale - metrics.ascent).abs() < 0.01);
+        assert!((-face.descent_unscaled() * scale - metrics.descent).abs() < 0.01);
+        assert!(metrics.descent > 0.);
+    }
+
+    #[test]
+    fn clusters_are_byte_offsets_of_graphemes() {
+        // The e and its combining accent make up one cluster.
+        let text = "ae\u{301}b";
+        let glyphs = rasterizer()
+            .shape_text(text, &Font::new("Pacifico", 100.))
+            .unwrap();
+
+        let mut clusters: Vec<usize> = glyphs.iter().map(|glyph| glyph.cluster).collect();
+        clusters.dedup();
+        assert_eq!(vec![0, 1, 4], clusters);
+    }
+
+    #[test]
+    fn glyphs_are_taken_from_the_first_family_that_has_them() {
+        let font = Font::new("Missing", 100.).fallback("Pacifico");
+        let glyphs = rasterizer().shape_text("ab", &font).unwrap();
+
+        assert_eq!(2, glyphs.len());
+        for glyph in glyphs {
+            assert_eq!(1, glyph.face);
+            // Not the missing glyph.
+            assert_ne!(0, glyph.glyph);
+        }
+    }
+
+    #[test]
+    fn shapes_extend_past_the_origin() {
+        // The tail of Pacifico's j reaches to the left of its origin and below the
+        // baseline.
+        let shape = rasterizer()
+            .measure_glyph(glyph('j'), &Font::new("Pacifico", 100.))
+            .unwrap();
+
+        assert_eq!(23, shape.left);
+        assert_eq!(37, shape.right);
+        assert_eq!(69, shape.ascent);
+        assert_eq!(46, shape.descent);
+    }
+
+    #[test]
+    fn glyphs_are_drawn_at_their_origin() {
+        let mut rasterizer = rasterizer();
+        let font = Font::new("Pacifico", 100.);
+        let shape = rasterizer.measure_glyph(glyph('j'), &font).unwrap();
+        let bitmap = rasterizer
+            .rasterize_glyph(glyph('j'), &font, &shape, [0., 0.])
+            .unwrap();
+
+        let alpha = |x: u32, y: u32| bitmap.data[((y * bitmap.width + x) * 4 + 3) as usize];
+        let column_has_ink = |x: u32| (0..bitmap.height).any(|y| alpha(x, y) > 0);
+        let row_has_ink = |y: u32| (0..bitmap.width).any(|x| alpha(x, y) > 0);
+
+        // The bitmap fits the glyph tightly, so nothing was drawn out of bounds.
+        assert!(column_has_ink(0));
+        assert!(column_has_ink(bitmap.width - 1));
+        assert!(row_has_ink(0));
+        assert!(row_has_ink(bitmap.height - 1));
+    }
+}
@@ -0,0 +1,345 @@
+use web_sys::WebGl2RenderingContext;
+
+/// Filter used when sampling glyphs from the atlas texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// Pixel-exact sampling. Appropriate when glyphs are drawn at the size they
+    /// were rasterized at.
+    Nearest,
+    /// Bilinear sampling. Glyphs should be padded (see
+    /// [`RendererOptions::glyph_padding`]) so that neighbours don't bleed in.
+    Linear,
+}
+
+impl TextureFilter {
+    pub(crate) fn gl_enum(self) -> i32 {
+        match self {
+            TextureFilter::Nearest => WebGl2RenderingContext::NEAREST as i32,
+            TextureFilter::Linear => WebGl2RenderingContext::LINEAR as i32,
+        }
+    }
+}
+
+/// Blend function the renderer sets on the context when it is created.
+///
+/// Glyphs are drawn with premultiplied alpha, so a custom blend function set
+/// with `Unchanged` should expect premultiplied colours.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Leave the blend function of the context as it is.
+    Unchanged,
+    /// `blendFunc(ONE, ONE_MINUS_SRC_ALPHA)`. The default.
+    PremultipliedAlpha,
+}
+
+impl BlendMode {
+    pub(crate) fn apply(self, gl: &WebGl2RenderingContext) {
+        match self {
+            BlendMode::Unchanged => (),
+            BlendMode::PremultipliedAlpha => gl.blend_func(
+                WebGl2RenderingContext::ONE,
+                WebGl2RenderingContext::ONE_MINUS_SRC_ALPHA,
+            ),
+        }
+    }
+}
+
+/// How the quads glyphs are drawn with are laid out in the vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuadMode {
+    /// One instance per glyph, whose corners are computed in the vertex shader.
+    Instanced,
+    /// Four vertices per glyph, which are drawn with a shared buffer of indices.
+    /// Each vertex has plain `a_position`, `a_tex_coord`, `a_layer` and `a_color`
+    /// attributes, for contexts where per-instance attributes are awkward.
+    Indexed,
+}
+
+/// Direction of the y axis of the pixel coordinates text is positioned in. The
+/// origin is a corner of the drawing buffer, and one unit is one logical pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum YAxis {
+    /// y is 0 at the bottom edge and increases upwards, like WebGL clip space.
+    Up,
+    /// y is 0 at the top edge and increases downwards, like the DOM and the 2D
+    /// canvas API.
+    Down,
+}
+
+impl YAxis {
+    /// 1 if y points up, -1 if it points down.
+    pub(crate) fn sign(self) -> f32 {
+        match self {
+            YAxis::Up => 1.,
+            YAxis::Down => -1.,
+        }
+    }
+}
+
+/// How glyphs are stored in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlyphMode {
+    /// Every font size gets its own glyph bitmaps, drawn pixel for pixel.
+    Bitmap,
+    /// Each face gets one signed distance field per glyph, rasterized at `size`
+    /// pixels, which is scaled to whatever size text is drawn at. Distances of up
+    /// to `spread` pixels (at the rasterized size) are encoded, which limits how
+    /// far glyphs can be scaled down before their edges soften. The texture is
+    /// always sampled with linear filtering in this mode.
+    ///
+    /// Only the outline of a glyph is kept, so colour glyphs such as emoji lose
+    /// their colours and are tinted with the text colour like any other glyph.
+    ///
+    /// `size: 48, spread: 6` is a reasonable starting point.
+    SignedDistanceField { size: u8, spread: u32 },
+}
+
+/// Dimensions and packing parameters of a glyph atlas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasOptions {
+    pub initial_width: u32,
+    pub initial_height: u32,
+    /// The atlas doubles in size when it fills up, until it reaches this width.
+    pub max_width: u32,
+    /// The atlas doubles in size when it fills up, until it reaches this height.
+    pub max_height: u32,
+    /// Once the atlas has reached its maximum size, pages of that size are added
+    /// when it fills up, until there are this many.
+    pub max_pages: u32,
+    /// Empty pixels left to the right of and below each glyph.
+    pub padding: u32,
+    pub glyph_mode: GlyphMode,
+    /// Number of horizontal and vertical subpixel positions bitmap glyphs are
+    /// rasterized at. Glyphs are snapped to the nearest of them, rather than to
+    /// the nearest whole pixel, which keeps spacing even and movement smooth at
+    /// the cost of rasterizing each glyph up to this many times.
+    pub subpixel_bins: [u8; 2],
+}
+
+impl Default for AtlasOptions {
+    fn default() -> Self {
+        AtlasOptions {
+            initial_width: 256,
+            initial_height: 256,
+            max_width: 2048,
+            max_height: 2048,
+            max_pages: 4,
+            padding: 0,
+            glyph_mode: GlyphMode::Bitmap,
+            subpixel_bins: [1, 1],
+        }
+    }
+}
+
+impl AtlasOptions {
+    /// The options with the atlas limited to textures of at most
+    /// `max_texture_size` pixels a side and `max_layers` pages, as supported
+    /// by the context, and to at least one pixel and page. The initial size is
+    /// limited to the maximum size.
+    pub(crate) fn clamped_to(mut self, max_texture_size: u32, max_layers: u32) -> AtlasOptions {
+        self.max_width = self.max_width.clamp(1, max_texture_size);
+        self.max_height = self.max_height.clamp(1, max_texture_size);
+        self.initial_width = self.initial_width.clamp(1, self.max_width);
+        self.initial_height = self.initial_height.clamp(1, self.max_height);
+        self.max_pages = self.max_pages.clamp(1, max_layers);
+        self.subpixel_bins = [self.subpixel_bins[0].max(1), self.subpixel_bins[1].max(1)];
+        self
+    }
+}
+
+/// Options for constructing a [`Renderer`](crate::Renderer).
+///
+/// ```ignore
+/// let options = RendererOptions::new()
+///     .atlas_size(512, 256)
+///     .glyph_padding(1)
+///     .texture_filter(TextureFilter::Linear);
+/// let renderer = Renderer::with_options(&gl, &options)?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct RendererOptions {
+    pub(crate) atlas: AtlasOptions,
+    pub(crate) texture_filter: TextureFilter,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) pixel_ratio: Option<f32>,
+    pub(crate) y_axis: YAxis,
+    pub(crate) quad_mode: QuadMode,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        RendererOptions {
+            atlas: AtlasOptions::default(),
+            texture_filter: TextureFilter::Nearest,
+            blend_mode: BlendMode::PremultipliedAlpha,
+            pixel_ratio: None,
+            y_axis: YAxis::Up,
+            quad_mode: QuadMode::Instanced,
+        }
+    }
+}
+
+impl RendererOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets every atlas option at once, replacing those set by `atlas_size`,
+    /// `glyph_mode` and the other atlas builders.
+    pub fn atlas_options(mut self, atlas: AtlasOptions) -> Self {
+        self.atlas = atlas;
+        self
+    }
+
+    /// Size the atlas starts out at, of at least one pixel. Limited to
+    /// `max_atlas_size`.
+    pub fn atlas_size(mut self, width: u32, height: u32) -> Self {
+        self.atlas.initial_width = width.max(1);
+        self.atlas.initial_height = height.max(1);
+        self
+    }
+
+    /// Size beyond which the atlas will not grow. Limited to the context's
+    /// `MAX_TEXTURE_SIZE`.
+    pub fn max_atlas_size(mut self, width: u32, height: u32) -> Self {
+        self.atlas.max_width = width.max(1);
+        self.atlas.max_height = height.max(1);
+        self
+    }
+
+    /// Number of pages (texture array layers) the atlas may use. Limited to the
+    /// context's `MAX_ARRAY_TEXTURE_LAYERS`.
+    pub fn max_atlas_pages(mut self, max_pages: u32) -> Self {
+        self.atlas.max_pages = max_pages.max(1);
+        self
+    }
+
+    pub fn glyph_padding(mut self, padding: u32) -> Self {
+        self.atlas.padding = padding;
+        self
+    }
+
+    pub fn glyph_mode(mut self, glyph_mode: GlyphMode) -> Self {
+        self.atlas.glyph_mode = glyph_mode;
+        self
+    }
+
+    /// Rasterizes bitmap glyphs at `horizontal` by `vertical` subpixel positions
+    /// (see [`AtlasOptions::subpixel_bins`]). `(4, 1)` is a reasonable choice for
+    /// horizontal text.
+    pub fn subpixel_positioning(mut self, horizontal: u8, vertical: u8) -> Self {
+        self.atlas.subpixel_bins = [horizontal.max(1), vertical.max(1)];
+        self
+    }
+
+    pub fn texture_filter(mut self, texture_filter: TextureFilter) -> Self {
+        self.texture_filter = texture_filter;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Direction of the y axis text is positioned along. Defaults to
+    /// [`YAxis::Up`].
+    pub fn y_axis(mut self, y_axis: YAxis) -> Self {
+        self.y_axis = y_axis;
+        self
+    }
+
+    /// Defaults to [`QuadMode::Instanced`].
+    pub fn quad_mode(mut self, quad_mode: QuadMode) -> Self {
+        self.quad_mode = quad_mode;
+        self
+    }
+
+    /// Number of device pixels per logical pixel. Text is positioned and sized in
+    /// logical pixels, and glyphs are rasterized at device pixels, so the drawing
+    /// buffer should be this many times as large as the canvas's CSS size.
+    /// Defaults to the window's `devicePixelRatio`, which is also used if
+    /// `pixel_ratio` isn't a positive number.
+    pub fn pixel_ratio(mut self, pixel_ratio: f32) -> Self {
+        self.pixel_ratio = valid_pixel_ratio(pixel_ratio);
+        self
+    }
+}
+
+/// `pixel_ratio` if it is usable as one: positive and finite.
+pub(crate) fn valid_pixel_ratio(pixel_ratio: f32) -> Option<f32> {
+    if pixel_ratio.is_finite() && pixel_ratio > 0. {
+        Some(pixel_ratio)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atlas_is_clamped_to_texture_limits() {
+        let options = RendererOptions::new()
+            .atlas_size(8192, 512)
+            .max_atlas_size(8192, 8192)
+            .max_atlas_pages(16);
+
+        let atlas = options.atlas.clamped_to(4096, 8);
+
+        assert_eq!((4096, 512), (atlas.initial_width, atlas.initial_height));
+        assert_eq!((4096, 4096), (atlas.max_width, atlas.max_height));
+        assert_eq!(8, atlas.max_pages);
+        // Limits above the options leave them as they are.
+        assert_eq!(atlas, atlas.clamped_to(16384, 256));
+    }
+
+    #[test]
+    fn atlas_is_at_least_one_pixel_and_page() {
+        let options = RendererOptions::new()
+            .atlas_size(0, 0)
+            .max_atlas_size(0, 0)
+            .max_atlas_pages(0);
+        assert_eq!(
+            (1, 1),
+            (options.atlas.initial_width, options.atlas.max_height)
+        );
+        assert_eq!(1, options.atlas.max_pages);
+
+        let atlas = AtlasOptions {
+            initial_width: 512,
+            initial_height: 0,
+            max_width: 256,
+            max_pages: 0,
+            subpixel_bins: [0, 2],
+            ..AtlasOptions::default()
+        }
+        .clamped_to(4096, 8);
+        assert_eq!((256, 1), (atlas.initial_width, atlas.initial_height));
+        assert_eq!(1, atlas.max_pages);
+        assert_eq!([1, 2], atlas.subpixel_bins);
+    }
+
+    #[test]
+    fn invalid_pixel_ratios_are_ignored() {
+        assert_eq!(Some(2.), RendererOptions::new().pixel_ratio(2.).pixel_ratio);
+        assert_eq!(None, RendererOptions::new().pixel_ratio(0.).pixel_ratio);
+        assert_eq!(None, RendererOptions::new().pixel_ratio(-1.).pixel_ratio);
+        assert_eq!(
+            None,
+            RendererOptions::new().pixel_ratio(f32::NAN).pixel_ratio
+        );
+    }
+
+    #[test]
+    fn atlas_options_replace_atlas_builders() {
+        let atlas = AtlasOptions {
+            max_pages: 2,
+            ..AtlasOptions::default()
+        };
+        let options = RendererOptions::new().glyph_padding(1).atlas_options(atlas);
+
+        assert_eq!(atlas, options.atlas);
+    }
+}
@@ -30,6 +30,15 @@ impl Rect {
             size: RectSize { height, width },
         }
     }
+
+    /// Whether the point is within the rect, including its right and bottom edges
+    /// (so that zero-sized rects contain their own corner).
+    fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && x <= self.x + self.size.width
+            && y <= self.y + self.size.height
+    }
 }
 
 pub struct PackingNode {
@@ -54,6 +63,41 @@ impl PackingNode {
             filled: false,
         }
     }
+
+    /// Returns a tree of the given size which contains this tree in its upper-left
+    /// corner, so that every rect already packed keeps its position. The new size
+    /// must be at least as large as the current one in both dimensions.
+    pub fn grow(self, width: u32, height: u32) -> PackingNode {
+        let mut node = self;
+        let old_width = node.rect.size.width;
+        let old_height = node.rect.size.height;
+
+        if width > old_width {
+            let right_rect = Rect::new(old_width, 0, width - old_width, old_height);
+            node = PackingNode {
+                children: Some((
+                    Box::new(node),
+                    Box::new(PackingNode::new_from_rect(right_rect)),
+                )),
+                rect: Rect::new(0, 0, width, old_height),
+                filled: false,
+            };
+        }
+
+        if height > old_height {
+            let bottom_rect = Rect::new(0, old_height, width, height - old_height);
+            node = PackingNode {
+                children: Some((
+                    Box::new(node),
+                    Box::new(PackingNode::new_from_rect(bottom_rect)),
+                )),
+                rect: Rect::new(0, 0, width, height),
+                filled: false,
+            };
+        }
+
+        node
+    }
 }
 
 impl PackingNode {
@@ -124,4 +168,126 @@ impl PackingNode {
 
         result
     }
+
+    /// Frees a rect previously returned by `insert_rect`, so that its space can be
+    /// reused. Returns `false` if no such rect is packed.
+    pub fn remove_rect(&mut self, x: u32, y: u32, rect: RectSize) -> bool {
+        if !self.rect.contains(x, y) {
+            return false;
+        }
+
+        if let Some((left, right)) = &mut self.children {
+            let removed = left.remove_rect(x, y, rect) || right.remove_rect(x, y, rect);
+
+            if removed && left.is_empty_leaf() && right.is_empty_leaf() {
+                // Both halves are free again, so merge them back into this node.
+                self.children = None;
+            }
+
+            return removed;
+        }
+
+        if self.filled && self.rect.x == x && self.rect.y == y && self.rect.size == rect {
+            self.filled = false;
+            return true;
+        }
+
+        false
+    }
+
+    fn is_empty_leaf(&self) -> bool {
+        self.children.is_none() && !self.filled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: u32, height: u32) -> RectSize {
+        RectSize { width, height }
+    }
+
+    #[test]
+    fn exact_fit_fills_node() {
+        let mut node = PackingNode::new(10, 20);
+
+        assert_eq!(Some((0, 0)), node.insert_rect(size(10, 20)));
+        assert_eq!(None, node.insert_rect(size(1, 1)));
+    }
+
+    #[test]
+    fn rects_do_not_overlap() {
+        let mut node = PackingNode::new(100, 100);
+        let mut positions = Vec::new();
+
+        while let Some(position) = node.insert_rect(size(10, 10)) {
+            positions.push(position);
+        }
+
+        assert_eq!(100, positions.len());
+        for (i, &(x1, y1)) in positions.iter().enumerate() {
+            assert!(x1 + 10 <= 100 && y1 + 10 <= 100);
+            for &(x2, y2) in &positions[i + 1..] {
+                assert!(x1 + 10 <= x2 || x2 + 10 <= x1 || y1 + 10 <= y2 || y2 + 10 <= y1);
+            }
+        }
+    }
+
+    #[test]
+    fn too_large_rect_does_not_fit() {
+        let mut node = PackingNode::new(10, 10);
+
+        assert_eq!(None, node.insert_rect(size(11, 5)));
+        assert_eq!(None, node.insert_rect(size(5, 11)));
+    }
+
+    #[test]
+    fn removed_rect_can_be_reused() {
+        let mut node = PackingNode::new(20, 20);
+        let positions: Vec<(u32, u32)> = (0..4)
+            .map(|_| node.insert_rect(size(10, 10)).unwrap())
+            .collect();
+        assert_eq!(None, node.insert_rect(size(10, 10)));
+
+        let (x, y) = positions[2];
+        assert!(node.remove_rect(x, y, size(10, 10)));
+        assert!(!node.remove_rect(x, y, size(10, 10)));
+
+        assert_eq!(Some((x, y)), node.insert_rect(size(10, 10)));
+    }
+
+    #[test]
+    fn removing_everything_merges_nodes() {
+        let mut node = PackingNode::new(20, 20);
+        let positions: Vec<(u32, u32)> = (0..4)
+            .map(|_| node.insert_rect(size(10, 10)).unwrap())
+            .collect();
+
+        for (x, y) in positions {
+            assert!(node.remove_rect(x, y, size(10, 10)));
+        }
+
+        assert!(node.is_empty_leaf());
+        assert_eq!(Some((0, 0)), node.insert_rect(size(20, 20)));
+    }
+
+    #[test]
+    fn grow_keeps_positions() {
+        let mut node = PackingNode::new(10, 10);
+        assert_eq!(Some((0, 0)), node.insert_rect(size(10, 10)));
+
+        let mut node = node.grow(20, 20);
+
+        assert!(node.remove_rect(0, 0, size(10, 10)));
+        assert_eq!(Some((0, 0)), node.insert_rect(size(10, 10)));
+
+        // The three new quadrants are free.
+        let mut positions: Vec<(u32, u32)> = (0..3)
+            .map(|_| node.insert_rect(size(10, 10)).unwrap())
+            .collect();
+        positions.sort();
+        assert_eq!(vec![(0, 10), (10, 0), (10, 10)], positions);
+        assert_eq!(None, node.insert_rect(size(10, 10)));
+    }
 }
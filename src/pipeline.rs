@@ -0,0 +1,846 @@
+use std::collections::BTreeMap;
+use std::ops::{Bound, Range};
+
+use crate::error::Result;
+use crate::glyph_atlas::{AtlasGlyph, DirtyRect, GlyphAtlas};
+use crate::layout::{layout_text, GlyphExtents, TextExtents, TextLayout};
+use crate::options::YAxis;
+use crate::quad::BlitQuad;
+use crate::style::TextStyle;
+use crate::Font;
+
+struct QueuedText {
+    text: String,
+    font: Font, // TODO: Use FontIndex, not Font
+    x: f32,
+    y: f32,
+    style: TextStyle,
+}
+
+/// Identifies text added with `Renderer::add_text`, which is drawn every frame
+/// until it is removed. Handles of removed text are ignored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextHandle(u64);
+
+/// Text that persists across frames, along with as much of the work of drawing
+/// it as is still valid.
+struct RetainedText {
+    text: QueuedText,
+    /// `None` if the text, font or style changed since it was laid out.
+    layout: Option<TextLayout>,
+    glyphs: Vec<AtlasGlyph>,
+    origins: Vec<[f32; 2]>,
+    /// Whether `glyphs` and `origins` are out of date, e.g. after the text moved.
+    needs_quads: bool,
+    /// Value of `GlyphAtlas::epoch` when the quads were built.
+    epoch: u64,
+    /// Where the quads of the text are in `TextPipeline::quads`.
+    quads: Range<usize>,
+}
+
+/// The CPU side of rendering: keeps the glyph atlas up to date with the queued
+/// and retained text and lays that text out as quads in pixel coordinates.
+/// Nothing here touches WebGL, so the pipeline can be driven (and tested)
+/// without a browser.
+pub struct TextPipeline {
+    atlas: GlyphAtlas,
+    queued_text: Vec<QueuedText>,
+    retained_text: BTreeMap<TextHandle, RetainedText>,
+    next_handle: u64,
+    /// Quads of the retained text, in the order it was added, followed by those
+    /// of the queued text.
+    quads: Vec<BlitQuad>,
+    /// Number of quads of retained text at the start of `quads`.
+    retained_quads: usize,
+    /// Quads that changed since the last call to `prepare`, which may since have
+    /// been removed.
+    changed: Option<Range<usize>>,
+    y_axis: YAxis,
+}
+
+impl TextPipeline {
+    pub fn new(atlas: GlyphAtlas) -> TextPipeline {
+        TextPipeline {
+            atlas,
+            queued_text: Vec::new(),
+            retained_text: BTreeMap::new(),
+            next_handle: 0,
+            quads: Vec::new(),
+            retained_quads: 0,
+            changed: None,
+            y_axis: YAxis::Up,
+        }
+    }
+
+    pub fn atlas(&self) -> &GlyphAtlas {
+        &self.atlas
+    }
+
+    /// Sets the number of device pixels per logical pixel. Text is positioned and
+    /// sized in logical pixels, and rasterized at device pixels.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.atlas.set_scale(scale);
+        self.invalidate_layouts();
+    }
+
+    pub fn set_y_axis(&mut self, y_axis: YAxis) {
+        self.y_axis = y_axis;
+        self.invalidate_layouts();
+    }
+
+    /// See `GlyphAtlas::invalidate_family`.
+    pub fn invalidate_family(&mut self, family: &str) {
+        self.atlas.invalidate_family(family);
+        self.invalidate_layouts();
+    }
+
+    /// Makes every retained text be laid out again on the next `prepare`.
+    fn invalidate_layouts(&mut self) {
+        for retained in self.retained_text.values_mut() {
+            retained.layout = None;
+        }
+    }
+
+    pub fn queue_text(&mut self, text: &str, font: &Font, x: f32, y: f32, style: &TextStyle) {
+        self.queued_text.push(QueuedText {
+            text: text.to_string(),
+            font: font.clone(),
+            x,
+            y,
+            style: style.clone(),
+        });
+    }
+
+    /// Adds text that is drawn on every call to `prepare` until it is removed.
+    /// Text is drawn in the order it was added, before any queued text.
+    pub fn add_text(
+        &mut self,
+        text: &str,
+        font: &Font,
+        x: f32,
+        y: f32,
+        style: &TextStyle,
+    ) -> TextHandle {
+        let handle = TextHandle(self.next_handle);
+        self.next_handle += 1;
+
+        self.retained_text.insert(
+            handle,
+            RetainedText {
+                text: QueuedText {
+                    text: text.to_string(),
+                    font: font.clone(),
+                    x,
+                    y,
+                    style: style.clone(),
+                },
+                layout: None,
+                glyphs: Vec::new(),
+                origins: Vec::new(),
+                needs_quads: true,
+                epoch: 0,
+                quads: self.retained_quads..self.retained_quads,
+            },
+        );
+
+        handle
+    }
+
+    pub fn set_text(&mut self, handle: TextHandle, text: &str) {
+        if let Some(retained) = self.retained_text.get_mut(&handle) {
+            if retained.text.text != text {
+                retained.text.text = text.to_string();
+                retained.layout = None;
+            }
+        }
+    }
+
+    pub fn set_text_font(&mut self, handle: TextHandle, font: &Font) {
+        if let Some(retained) = self.retained_text.get_mut(&handle) {
+            if retained.text.font != *font {
+                retained.text.font = font.clone();
+                retained.layout = None;
+            }
+        }
+    }
+
+    pub fn set_text_style(&mut self, handle: TextHandle, style: &TextStyle) {
+        if let Some(retained) = self.retained_text.get_mut(&handle) {
+            if retained.text.style != *style {
+                retained.text.style = style.clone();
+                retained.layout = None;
+            }
+        }
+    }
+
+    /// Moves text without laying it out again.
+    pub fn set_text_position(&mut self, handle: TextHandle, x: f32, y: f32) {
+        if let Some(retained) = self.retained_text.get_mut(&handle) {
+            if retained.text.x != x || retained.text.y != y {
+                retained.text.x = x;
+                retained.text.y = y;
+                retained.needs_quads = true;
+            }
+        }
+    }
+
+    pub fn remove_text(&mut self, handle: TextHandle) {
+        if let Some(retained) = self.retained_text.remove(&handle) {
+            self.replace_quads(handle, retained.quads, Vec::new());
+        }
+    }
+
+    /// Replaces the quads of a retained text, moving the quads of the text after
+    /// it if the number of quads changed.
+    fn replace_quads(&mut self, handle: TextHandle, range: Range<usize>, quads: Vec<BlitQuad>) {
+        let start = range.start;
+        let old_len = range.len();
+        let new_len = quads.len();
+        // Queued text is drawn again anyway.
+        self.quads.truncate(self.retained_quads);
+        self.quads.splice(range, quads);
+
+        if new_len == old_len {
+            self.changed = Some(union(self.changed.take(), start..start + new_len));
+            return;
+        }
+
+        let after = (Bound::Excluded(handle), Bound::Unbounded);
+        for (_, retained) in self.retained_text.range_mut(after) {
+            retained.quads.start = retained.quads.start + new_len - old_len;
+            retained.quads.end = retained.quads.end + new_len - old_len;
+        }
+        self.retained_quads = self.retained_quads + new_len - old_len;
+        // Every quad after the replaced ones moved.
+        self.changed = Some(union(self.changed.take(), start..self.retained_quads));
+    }
+
+    fn lay_out(&mut self, text: &str, font: &Font, style: &TextStyle) -> Result<TextLayout> {
+        let shaped = self.atlas.shape_text(text, font)?;
+        let metrics = self.atlas.font_metrics(font)?;
+
+        Ok(layout_text(
+            text,
+            &shaped,
+            self.atlas.glyph_scale(font),
+            &metrics,
+            style,
+        ))
+    }
+
+    /// Measures text as `prepare` would lay it out, before its transform,
+    /// without adding its glyphs to the atlas.
+    pub fn measure_text(
+        &mut self,
+        text: &str,
+        font: &Font,
+        style: &TextStyle,
+    ) -> Result<TextExtents> {
+        let layout = self.lay_out(text, font, style)?;
+        let glyph_scale = self.atlas.glyph_scale(font);
+
+        let mut ink: Option<([f32; 2], [f32; 2])> = None;
+        for positioned in &layout.glyphs {
+            let shape = self
+                .atlas
+                .measure_glyph(positioned.glyph, positioned.face, font)?;
+            if shape.glyph_width() == 0 || shape.height() == 0 {
+                continue;
+            }
+
+            let min = [
+                positioned.x - shape.left as f32 * glyph_scale,
+                positioned.y - shape.descent as f32 * glyph_scale,
+            ];
+            let max = [
+                positioned.x + shape.right as f32 * glyph_scale,
+                positioned.y + shape.ascent as f32 * glyph_scale,
+            ];
+            ink = Some(match ink {
+                Some((ink_min, ink_max)) => (
+                    [ink_min[0].min(min[0]), ink_min[1].min(min[1])],
+                    [ink_max[0].max(max[0]), ink_max[1].max(max[1])],
+                ),
+                None => (min, max),
+            });
+        }
+        let (ink_min, ink_max) = ink.unwrap_or(([0., 0.], [0., 0.]));
+        let last_baseline =
+            layout.baseline - layout.num_lines.saturating_sub(1) as f32 * layout.line_height;
+
+        let ascent = (ink_max[1] - layout.baseline).max(0.);
+        let descent = (last_baseline - ink_min[1]).max(0.);
+
+        // Positions so far have y pointing up.
+        let sign = self.y_axis.sign();
+        let (ink_min, ink_max) = match self.y_axis {
+            YAxis::Up => (ink_min, ink_max),
+            YAxis::Down => ([ink_min[0], -ink_max[1]], [ink_max[0], -ink_min[1]]),
+        };
+
+        Ok(TextExtents {
+            width: layout.width,
+            num_lines: layout.num_lines,
+            ascent,
+            descent,
+            ink_min,
+            ink_max,
+            glyphs: layout
+                .glyphs
+                .iter()
+                .map(|positioned| GlyphExtents {
+                    cluster: positioned.cluster,
+                    x: positioned.x,
+                    y: sign * positioned.y,
+                    advance: positioned.advance,
+                })
+                .collect(),
+        })
+    }
+
+    /// Where a glyph whose origin is at the given position (in logical pixels)
+    /// is drawn from: the position snapped to the nearest whole device pixel, or
+    /// subpixel bin of one, and the bin.
+    fn place_glyph(&self, x: f32, y: f32) -> ([f32; 2], [u8; 2]) {
+        let scale = self.atlas.scale();
+        let sign = self.y_axis.sign();
+
+        match self.atlas.subpixel_bins() {
+            Some([horizontal, vertical]) => {
+                let (x, x_bin) = snap(x * scale, horizontal);
+                // Vertical bins count down from the pixel, like rows of a bitmap.
+                let (down, y_bin) = snap(-sign * y * scale, vertical);
+                ([x / scale, -sign * down / scale], [x_bin, y_bin])
+            }
+            None => ([x, y], [0, 0]),
+        }
+    }
+
+    /// The glyphs of laid out text, as they are stored in the atlas, and where
+    /// their origins are.
+    fn place_text(
+        &self,
+        queued: &QueuedText,
+        layout: &TextLayout,
+    ) -> (Vec<AtlasGlyph>, Vec<[f32; 2]>) {
+        layout
+            .glyphs
+            .iter()
+            .map(|positioned| {
+                let x = queued.x + positioned.x;
+                let y = queued.y + self.y_axis.sign() * positioned.y;
+                // Transformed glyphs don't line up with pixels, so there is
+                // nothing to snap them to.
+                let (origin, subpixel_bin) = match queued.style.transform {
+                    Some(_) => ([x, y], [0, 0]),
+                    None => self.place_glyph(x, y),
+                };
+                let glyph = AtlasGlyph {
+                    glyph: positioned.glyph,
+                    face: positioned.face,
+                    subpixel_bin,
+                };
+
+                (glyph, origin)
+            })
+            .unzip()
+    }
+
+    /// Quads for placed glyphs of a text, which must be in the atlas.
+    fn text_quads(
+        &self,
+        queued: &QueuedText,
+        glyphs: &[AtlasGlyph],
+        origins: &[[f32; 2]],
+    ) -> Result<Vec<BlitQuad>> {
+        let QueuedText { font, style, .. } = queued;
+        let anchor = [queued.x + style.anchor[0], queued.y + style.anchor[1]];
+        let glyph_scale = self.atlas.glyph_scale(font);
+        let sign = self.y_axis.sign();
+
+        let mut quads = Vec::with_capacity(glyphs.len());
+        for (glyph, [origin_x, origin_y]) in glyphs.iter().zip(origins) {
+            let entry = self.atlas.get_entry(*glyph, font)?;
+
+            // Tinting with white leaves the colours of colour glyphs as they
+            // are, while keeping the opacity of the text.
+            let color = if entry.is_color {
+                [1., 1., 1., style.color[3]]
+            } else {
+                style.color
+            };
+
+            let (tex_upper_left, tex_lower_right) = entry.texture_scaled_bounds(self.atlas.size());
+
+            let glyph_width = entry.glyph_shape.glyph_width() as f32 * glyph_scale;
+            let glyph_height = entry.glyph_shape.height() as f32 * glyph_scale;
+            let glyph_offset = entry.glyph_shape.descent as f32 * glyph_scale;
+
+            let glyph_left = origin_x - entry.glyph_shape.left as f32 * glyph_scale;
+            let glyph_bottom = origin_y - sign * glyph_offset;
+
+            let blit_upper_left = [glyph_left, glyph_bottom + sign * glyph_height];
+            let blit_lower_right = [glyph_left + glyph_width, glyph_bottom];
+
+            let mut quad = BlitQuad::new(
+                blit_upper_left,
+                blit_lower_right,
+                tex_upper_left,
+                tex_lower_right,
+                entry.page as f32,
+                color,
+            );
+            if let Some(transform) = &style.transform {
+                quad.transform(|[x, y]| {
+                    let [x, y] = transform.apply([x - anchor[0], y - anchor[1]]);
+                    [x + anchor[0], y + anchor[1]]
+                });
+            }
+            quads.push(quad);
+        }
+
+        Ok(quads)
+    }
+
+    /// Shapes and lays out the queued text and any retained text that changed,
+    /// adds any of their glyphs that are missing to the atlas, and updates the
+    /// quads, in logical pixels. Empties the queue.
+    /// Returns the regions of the atlas that were modified.
+    pub fn prepare(&mut self) -> Result<Vec<DirtyRect>> {
+        let queued_text = std::mem::take(&mut self.queued_text);
+        let mut retained_text = std::mem::take(&mut self.retained_text);
+        let result = self.prepare_text(&mut retained_text, &queued_text);
+        self.retained_text = retained_text;
+        let (dirty_rects, rebuilt, queued_quads) = result?;
+
+        let epoch = self.atlas.epoch();
+        for (handle, quads) in rebuilt {
+            let range = self.retained_text[&handle].quads.clone();
+            let len = quads.len();
+            self.replace_quads(handle, range.clone(), quads);
+
+            let retained = self.retained_text.get_mut(&handle).unwrap();
+            retained.quads = range.start..range.start + len;
+            retained.needs_quads = false;
+            retained.epoch = epoch;
+        }
+
+        self.quads.truncate(self.retained_quads);
+        self.quads.extend(queued_quads);
+
+        Ok(dirty_rects)
+    }
+
+    /// Does the work of `prepare` that can fail: returns the modified regions of
+    /// the atlas, the new quads of retained text that needs them, and the quads
+    /// of the queued text.
+    #[allow(clippy::type_complexity)]
+    fn prepare_text(
+        &mut self,
+        retained_text: &mut BTreeMap<TextHandle, RetainedText>,
+        queued_text: &[QueuedText],
+    ) -> Result<(
+        Vec<DirtyRect>,
+        Vec<(TextHandle, Vec<BlitQuad>)>,
+        Vec<BlitQuad>,
+    )> {
+        for retained in retained_text.values_mut() {
+            if retained.layout.is_none() {
+                let QueuedText {
+                    text, font, style, ..
+                } = &retained.text;
+                retained.layout = Some(self.lay_out(text, font, style)?);
+                retained.needs_quads = true;
+            }
+            if retained.needs_quads {
+                let layout = retained.layout.as_ref().unwrap();
+                let (glyphs, origins) = self.place_text(&retained.text, layout);
+                retained.glyphs = glyphs;
+                retained.origins = origins;
+            }
+        }
+
+        let mut glyphs: Vec<Vec<AtlasGlyph>> = Vec::with_capacity(queued_text.len());
+        let mut origins: Vec<Vec<[f32; 2]>> = Vec::with_capacity(queued_text.len());
+        for queued in queued_text {
+            let layout = self.lay_out(&queued.text, &queued.font, &queued.style)?;
+            let (text_glyphs, text_origins) = self.place_text(queued, &layout);
+            glyphs.push(text_glyphs);
+            origins.push(text_origins);
+        }
+
+        // Retained text is included even if it didn't change, so that its glyphs
+        // aren't evicted.
+        let dirty_rects = self.atlas.prepare_glyphs(
+            retained_text
+                .values()
+                .map(|retained| (retained.glyphs.as_slice(), &retained.text.font))
+                .chain(
+                    glyphs
+                        .iter()
+                        .zip(queued_text)
+                        .map(|(glyphs, queued)| (glyphs.as_slice(), &queued.font)),
+                )
+                .collect(),
+        )?;
+
+        // Quads of retained text are rebuilt if it changed, or if the glyphs it
+        // uses moved in the atlas.
+        let epoch = self.atlas.epoch();
+        let mut rebuilt = Vec::new();
+        for (handle, retained) in retained_text.iter() {
+            if retained.needs_quads || retained.epoch != epoch {
+                let quads = self.text_quads(&retained.text, &retained.glyphs, &retained.origins)?;
+                rebuilt.push((*handle, quads));
+            }
+        }
+
+        let mut queued_quads = Vec::new();
+        for ((queued, glyphs), origins) in queued_text.iter().zip(&glyphs).zip(&origins) {
+            queued_quads.extend(self.text_quads(queued, glyphs, origins)?);
+        }
+
+        Ok((dirty_rects, rebuilt, queued_quads))
+    }
+
+    /// Quads of the text retained or queued before the last call to `prepare`.
+    pub fn quads(&self) -> &[BlitQuad] {
+        &self.quads
+    }
+
+    /// Takes the range of `quads` that changed since the last call, which always
+    /// includes the quads of queued text.
+    pub fn take_changed_quads(&mut self) -> Range<usize> {
+        let changed = union(self.changed.take(), self.retained_quads..self.quads.len());
+        changed.start.min(self.quads.len())..changed.end.min(self.quads.len())
+    }
+}
+
+/// The smallest range covering both ranges.
+fn union(a: Option<Range<usize>>, b: Range<usize>) -> Range<usize> {
+    match a {
+        Some(a) => a.start.min(b.start)..a.end.max(b.end),
+        None => b,
+    }
+}
+
+/// Rounds a position in pixels to the nearest of `bins` evenly spaced positions
+/// per pixel. Returns the whole pixel at or before it, and which of the
+/// positions it is.
+fn snap(position: f32, bins: u8) -> (f32, u8) {
+    let bins = bins as i64;
+    let steps = (position * bins as f32).round() as i64;
+
+    (steps.div_euclid(bins) as f32, steps.rem_euclid(bins) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::AtlasOptions;
+    use crate::rasterizer::testing::BoxRasterizer;
+    use crate::rasterizer::GlyphId;
+    use crate::transform::testing::assert_close;
+    use crate::transform::Affine;
+
+    fn pipeline() -> TextPipeline {
+        pipeline_with_rasterizer(BoxRasterizer::new(8, 6, 2, 9.))
+    }
+
+    fn pipeline_with_rasterizer(rasterizer: BoxRasterizer) -> TextPipeline {
+        let options = AtlasOptions {
+            initial_width: 64,
+            initial_height: 64,
+            ..AtlasOptions::default()
+        };
+
+        TextPipeline::new(GlyphAtlas::new(&options, Box::new(rasterizer)))
+    }
+
+    #[test]
+    fn one_quad_per_character() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        pipeline.queue_text("abc", &font, 0., 0., &TextStyle::default());
+        pipeline.queue_text("aa", &font, 0., 50., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+
+        assert_eq!(5, pipeline.quads().len());
+
+        // The queue is emptied by `prepare`.
+        pipeline.prepare().unwrap();
+        assert!(pipeline.quads().is_empty());
+    }
+
+    #[test]
+    fn quad_corners() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        pipeline.queue_text("ab", &font, 10., 20., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        // Glyphs are 8 pixels wide with 6 above and 2 below the baseline, and
+        // advance by 9 pixels.
+        assert_close([10., 26.], quads[0].corner([0., 0.]));
+        assert_close([18., 26.], quads[0].corner([1., 0.]));
+        assert_close([10., 18.], quads[0].corner([0., 1.]));
+        assert_close([18., 18.], quads[0].corner([1., 1.]));
+        assert_close([27., 18.], quads[1].corner([1., 1.]));
+        assert_close([19., 26.], quads[1].corner([0., 0.]));
+    }
+
+    #[test]
+    fn transforms_are_applied_around_the_anchor() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        let rotate = Affine::rotate(std::f32::consts::FRAC_PI_2);
+        pipeline.queue_text("a", &font, 10., 20., &TextStyle::new().transform(rotate));
+        pipeline.queue_text(
+            "a",
+            &font,
+            10.,
+            20.,
+            &TextStyle::new().transform(rotate).anchor([4., 0.]),
+        );
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        // Unrotated, the glyph would span x 10..18 and y 18..26.
+        assert_close([12., 28.], quads[0].corner([1., 1.]));
+        assert_close([4., 20.], quads[0].corner([0., 0.]));
+        assert_close([16., 24.], quads[1].corner([1., 1.]));
+    }
+
+    #[test]
+    fn y_axis_can_point_down() {
+        let mut pipeline = pipeline();
+        pipeline.set_y_axis(YAxis::Down);
+        let font = Font::new("Test", 10.);
+        pipeline.queue_text("a\na", &font, 10., 20., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        // Glyphs extend 6 pixels up from the baseline, and lines go down.
+        assert_close([18., 22.], quads[0].corner([1., 1.]));
+        assert_close([10., 14.], quads[0].corner([0., 0.]));
+        assert_close([10., 26.], quads[1].corner([0., 0.]));
+
+        let extents = pipeline
+            .measure_text("a", &font, &TextStyle::default())
+            .unwrap();
+        assert_eq!([0., -6.], extents.ink_min);
+        assert_eq!([8., 2.], extents.ink_max);
+    }
+
+    #[test]
+    fn positions_are_scaled_to_device_pixels() {
+        let mut pipeline = pipeline();
+        pipeline.set_scale(2.);
+        pipeline.queue_text(
+            "a",
+            &Font::new("Test", 10.),
+            10.25,
+            20.,
+            &TextStyle::default(),
+        );
+
+        pipeline.prepare().unwrap();
+        let quad = &pipeline.quads()[0];
+
+        // The glyph is 8 by 8 device pixels, so 4 by 4 logical pixels. Its left
+        // edge is snapped to the nearest device pixel, at 10.5.
+        assert_close([14.5, 19.], quad.corner([1., 1.]));
+        assert_close([10.5, 23.], quad.corner([0., 0.]));
+    }
+
+    #[test]
+    fn glyphs_snap_to_subpixel_bins() {
+        let options = AtlasOptions {
+            initial_width: 64,
+            initial_height: 64,
+            subpixel_bins: [4, 1],
+            ..AtlasOptions::default()
+        };
+        let rasterizer = BoxRasterizer::new(8, 6, 2, 9.);
+        let mut pipeline = TextPipeline::new(GlyphAtlas::new(&options, Box::new(rasterizer)));
+        let font = Font::new("Test", 10.);
+        let style = TextStyle::default();
+        pipeline.queue_text("a", &font, 10.3, 20.2, &style);
+        pipeline.queue_text("a", &font, 10.6, 20., &style);
+        pipeline.queue_text("a", &font, 11., 20., &style);
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        // 10.3 is nearest to 10.25 and 10.6 to 10.5, so both are drawn from pixel
+        // 10, with glyphs rasterized a quarter and half a pixel to the right.
+        // There is a single vertical bin, so y is rounded.
+        assert_close([10., 18.], quads[0].corner([0., 1.]));
+        assert_close([10., 18.], quads[1].corner([0., 1.]));
+        assert_close([11., 18.], quads[2].corner([0., 1.]));
+        // Glyphs have an extra pixel on the right to move into.
+        assert_close([19., 18.], quads[0].corner([1., 1.]));
+
+        assert_ne!(quads[0].tex_position, quads[1].tex_position);
+        assert_ne!(quads[0].tex_position, quads[2].tex_position);
+        assert_ne!(quads[1].tex_position, quads[2].tex_position);
+    }
+
+    #[test]
+    fn quad_texture_coordinates() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        pipeline.queue_text("a", &font, 0., 0., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+
+        let entry = pipeline
+            .atlas()
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .unwrap();
+        let (upper_left, lower_right) = entry.texture_scaled_bounds((64, 64));
+        let quad = &pipeline.quads()[0];
+
+        assert_eq!(upper_left, quad.tex_position);
+        assert_close(
+            lower_right,
+            [
+                quad.tex_position[0] + quad.tex_size[0],
+                quad.tex_position[1] + quad.tex_size[1],
+            ],
+        );
+        assert_eq!(0., quad.layer);
+    }
+
+    #[test]
+    fn quads_take_the_text_color() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        let red = TextStyle::new().color([1., 0., 0., 1.]);
+        pipeline.queue_text("a", &font, 0., 0., &red);
+        pipeline.queue_text("a", &font, 0., 20., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        // Both colours share one glyph in the atlas.
+        assert_eq!(quads[0].tex_position, quads[1].tex_position);
+        assert_eq!([1., 0., 0., 1.], quads[0].color);
+        assert_eq!([0., 0., 0., 1.], quads[1].color);
+    }
+
+    #[test]
+    fn color_glyphs_are_not_tinted() {
+        let mut rasterizer = BoxRasterizer::new(8, 6, 2, 9.);
+        rasterizer.color_glyphs.push('b' as GlyphId);
+        let mut pipeline = pipeline_with_rasterizer(rasterizer);
+        let style = TextStyle::new().color([1., 0., 0., 0.5]);
+        pipeline.queue_text("ab", &Font::new("Test", 10.), 0., 0., &style);
+
+        pipeline.prepare().unwrap();
+        let quads = pipeline.quads();
+
+        assert_eq!([1., 0., 0., 0.5], quads[0].color);
+        assert_eq!([1., 1., 1., 0.5], quads[1].color);
+    }
+
+    #[test]
+    fn measured_text_matches_quads() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+
+        let extents = pipeline
+            .measure_text("ab", &font, &TextStyle::default())
+            .unwrap();
+
+        assert_eq!(18., extents.width);
+        assert_eq!(6., extents.ascent);
+        assert_eq!(2., extents.descent);
+        assert_eq!([0., -2.], extents.ink_min);
+        assert_eq!([17., 6.], extents.ink_max);
+        assert_eq!(9., extents.glyphs[1].x);
+        assert_eq!(1, extents.glyphs[1].cluster);
+
+        // Measuring doesn't add glyphs to the atlas.
+        assert!(pipeline
+            .atlas()
+            .get_entry(AtlasGlyph::new('a' as GlyphId, 0), &font)
+            .is_err());
+    }
+
+    #[test]
+    fn retained_text_persists_across_frames() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        pipeline.add_text("ab", &font, 10., 20., &TextStyle::default());
+
+        pipeline.prepare().unwrap();
+        assert_eq!(2, pipeline.quads().len());
+        assert_eq!(0..2, pipeline.take_changed_quads());
+
+        pipeline.queue_text("c", &font, 0., 0., &TextStyle::default());
+        pipeline.prepare().unwrap();
+        assert_eq!(3, pipeline.quads().len());
+        // Only the queued text has to be uploaded again.
+        assert_eq!(2..3, pipeline.take_changed_quads());
+
+        pipeline.prepare().unwrap();
+        assert_eq!(2, pipeline.quads().len());
+        assert_eq!(2..2, pipeline.take_changed_quads());
+    }
+
+    #[test]
+    fn only_changed_retained_text_is_updated() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        let first = pipeline.add_text("ab", &font, 10., 20., &TextStyle::default());
+        let second = pipeline.add_text("cd", &font, 10., 40., &TextStyle::default());
+        pipeline.prepare().unwrap();
+        pipeline.take_changed_quads();
+
+        pipeline.set_text_position(second, 20., 40.);
+        pipeline.prepare().unwrap();
+        assert_eq!(2..4, pipeline.take_changed_quads());
+        assert_close([20., 38.], pipeline.quads()[2].corner([0., 1.]));
+
+        // The second text moves along when the first one gets longer.
+        pipeline.set_text(first, "abc");
+        pipeline.prepare().unwrap();
+        assert_eq!(0..5, pipeline.take_changed_quads());
+        assert_close([20., 38.], pipeline.quads()[3].corner([0., 1.]));
+
+        pipeline.remove_text(first);
+        pipeline.prepare().unwrap();
+        assert_eq!(2, pipeline.quads().len());
+        assert_eq!(0..2, pipeline.take_changed_quads());
+        assert_close([20., 38.], pipeline.quads()[0].corner([0., 1.]));
+
+        // Removed text can't be changed.
+        pipeline.set_text(first, "a");
+        pipeline.prepare().unwrap();
+        assert_eq!(2, pipeline.quads().len());
+    }
+
+    #[test]
+    fn retained_quads_follow_the_atlas_when_it_grows() {
+        let mut pipeline = pipeline();
+        let font = Font::new("Test", 10.);
+        pipeline.add_text("a", &font, 0., 0., &TextStyle::default());
+        pipeline.prepare().unwrap();
+
+        // More glyphs than fit on a 64 by 64 page.
+        let many_glyphs: String = ('\u{100}'..'\u{180}').collect();
+        pipeline.queue_text(&many_glyphs, &font, 0., 0., &TextStyle::default());
+        pipeline.queue_text("a", &font, 0., 0., &TextStyle::default());
+        pipeline.prepare().unwrap();
+        assert!(pipeline.atlas().size() != (64, 64));
+
+        let quads = pipeline.quads();
+        assert_eq!(quads[quads.len() - 1], quads[0]);
+    }
+}
@@ -0,0 +1,193 @@
+use web_sys::{WebGl2RenderingContext, WebGlProgram};
+
+/// A glyph as it is drawn: one instance of a quad whose four corners are
+/// computed in the vertex shader.
+///
+/// The quad is a parallelogram, so that transformed text can be drawn, spanned
+/// by two edges from the corner the upper left of the texture rect is drawn at.
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BlitQuad {
+    pub position: [f32; 2],
+    /// Edge along which the texture rect goes right.
+    pub x_edge: [f32; 2],
+    /// Edge along which the texture rect goes down.
+    pub y_edge: [f32; 2],
+    pub tex_position: [f32; 2],
+    pub tex_size: [f32; 2],
+    /// Texture array layer (atlas page) to sample from.
+    pub layer: f32,
+    /// Straight RGBA colour the glyph is tinted with.
+    pub color: [f32; 4],
+}
+
+impl BlitQuad {
+    /// A quad drawing the texture rect from `tex_upper_left` to `tex_lower_right`
+    /// into the rect from `upper_left` to `lower_right`.
+    pub fn new(
+        upper_left: [f32; 2],
+        lower_right: [f32; 2],
+        tex_upper_left: [f32; 2],
+        tex_lower_right: [f32; 2],
+        layer: f32,
+        color: [f32; 4],
+    ) -> BlitQuad {
+        BlitQuad {
+            position: upper_left,
+            x_edge: [lower_right[0] - upper_left[0], 0.],
+            y_edge: [0., lower_right[1] - upper_left[1]],
+            tex_position: tex_upper_left,
+            tex_size: [
+                tex_lower_right[0] - tex_upper_left[0],
+                tex_lower_right[1] - tex_upper_left[1],
+            ],
+            layer,
+            color,
+        }
+    }
+
+    /// The corner at the given fraction along the x and y edges.
+    pub fn corner(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        [
+            self.position[0] + x * self.x_edge[0] + y * self.y_edge[0],
+            self.position[1] + x * self.x_edge[1] + y * self.y_edge[1],
+        ]
+    }
+
+    /// Moves the quad to where an affine `transform` maps it.
+    pub fn transform(&mut self, transform: impl Fn([f32; 2]) -> [f32; 2]) {
+        let position = transform(self.position);
+        let x_end = transform(self.corner([1., 0.]));
+        let y_end = transform(self.corner([0., 1.]));
+
+        self.position = position;
+        self.x_edge = [x_end[0] - position[0], x_end[1] - position[1]];
+        self.y_edge = [y_end[0] - position[0], y_end[1] - position[1]];
+    }
+
+    /// The corners of the quad, in the order of a triangle strip: upper left,
+    /// upper right, lower left and lower right of the texture rect.
+    pub fn vertices(&self) -> [BlitVertex; 4] {
+        let vertex = |corner: [f32; 2]| BlitVertex {
+            position: self.corner(corner),
+            tex_coord: [
+                self.tex_position[0] + corner[0] * self.tex_size[0],
+                self.tex_position[1] + corner[1] * self.tex_size[1],
+            ],
+            layer: self.layer,
+            color: self.color,
+        };
+
+        [
+            vertex([0., 0.]),
+            vertex([1., 0.]),
+            vertex([0., 1.]),
+            vertex([1., 1.]),
+        ]
+    }
+
+    /// Points the per-instance attributes of the program at a bound buffer of
+    /// quads. The divisors are part of the state of the bound vertex array.
+    pub(crate) fn describe(gl: &WebGl2RenderingContext, program: &WebGlProgram) {
+        let mut offset = 0;
+
+        // Every attribute is made up of f32s, so we only need the component counts.
+        for (attribute, size) in &[
+            ("a_position", 2),
+            ("a_x_edge", 2),
+            ("a_y_edge", 2),
+            ("a_tex_position", 2),
+            ("a_tex_size", 2),
+            ("a_layer", 1),
+            ("a_color", 4),
+        ] {
+            let location = gl.get_attrib_location(program, attribute) as u32;
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                *size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                std::mem::size_of::<BlitQuad>() as i32,
+                offset,
+            );
+            gl.enable_vertex_attrib_array(location);
+            gl.vertex_attrib_divisor(location, 1);
+
+            offset += size * std::mem::size_of::<f32>() as i32;
+        }
+    }
+}
+
+/// A corner of a quad, for `QuadMode::Indexed`.
+#[repr(C)]
+#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Debug, PartialEq)]
+pub(crate) struct BlitVertex {
+    pub position: [f32; 2],
+    pub tex_coord: [f32; 2],
+    /// Texture array layer (atlas page) to sample from.
+    pub layer: f32,
+    /// Straight RGBA colour the glyph is tinted with.
+    pub color: [f32; 4],
+}
+
+impl BlitVertex {
+    /// Points the per-vertex attributes of the program at a bound buffer of
+    /// vertices.
+    pub(crate) fn describe(gl: &WebGl2RenderingContext, program: &WebGlProgram) {
+        let mut offset = 0;
+
+        for (attribute, size) in &[
+            ("a_position", 2),
+            ("a_tex_coord", 2),
+            ("a_layer", 1),
+            ("a_color", 4),
+        ] {
+            let location = gl.get_attrib_location(program, attribute) as u32;
+            gl.vertex_attrib_pointer_with_i32(
+                location,
+                *size,
+                WebGl2RenderingContext::FLOAT,
+                false,
+                std::mem::size_of::<BlitVertex>() as i32,
+                offset,
+            );
+            gl.enable_vertex_attrib_array(location);
+
+            offset += size * std::mem::size_of::<f32>() as i32;
+        }
+    }
+}
+
+/// Indices of the two triangles of each of `num_quads` quads, whose vertices
+/// are laid out as by `BlitQuad::vertices`.
+pub(crate) fn quad_indices(num_quads: usize) -> Vec<u32> {
+    (0..num_quads as u32)
+        .flat_map(|quad| {
+            let first = quad * 4;
+            vec![first, first + 1, first + 2, first + 1, first + 3, first + 2]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertices_are_the_corners_of_the_quad() {
+        let quad = BlitQuad::new([10., 30.], [18., 20.], [0., 0.], [0.5, 0.25], 1., [1.; 4]);
+        let vertices = quad.vertices();
+
+        assert_eq!([10., 30.], vertices[0].position);
+        assert_eq!([18., 30.], vertices[1].position);
+        assert_eq!([10., 20.], vertices[2].position);
+        assert_eq!([18., 20.], vertices[3].position);
+        assert_eq!([0.5, 0.25], vertices[3].tex_coord);
+        assert_eq!(1., vertices[3].layer);
+    }
+
+    #[test]
+    fn indices_draw_two_triangles_per_quad() {
+        assert_eq!(vec![0, 1, 2, 1, 3, 2, 4, 5, 6, 5, 7, 6], quad_indices(2));
+    }
+}
@@ -0,0 +1,313 @@
+use crate::error::Result;
+use crate::packing::RectSize;
+use crate::Font;
+
+/// Identifies a glyph within a font. What the number refers to is up to the
+/// rasterizer; it only has to be consistent between `shape_text` and the other
+/// methods.
+pub type GlyphId = u32;
+
+/// A source of glyphs, glyph metrics and pixels for the atlas.
+///
+/// Text is first shaped into positioned glyphs. The atlas then asks the
+/// rasterizer to measure every glyph it hasn't seen before, reserves space for
+/// it based on the returned shape, and then asks the rasterizer to draw it.
+pub trait GlyphRasterizer {
+    /// Converts text into the glyphs that represent it in the given font, in
+    /// visual order, positioned relative to the start of the text. Each glyph
+    /// records which of the font's families it was taken from.
+    fn shape_text(&mut self, text: &str, font: &Font) -> Result<Vec<ShapedGlyph>>;
+
+    /// Returns the vertical metrics of the given font's first family.
+    fn font_metrics(&mut self, font: &Font) -> Result<FontMetrics>;
+
+    /// Returns the bounding box and advance of a glyph in the given font, which
+    /// has only the family the glyph was shaped with (see `Font::face`).
+    fn measure_glyph(&mut self, glyph: GlyphId, font: &Font) -> Result<GlyphShape>;
+
+    /// Draws a glyph previously measured with `measure_glyph` into a bitmap of
+    /// `shape.glyph_width()` by `shape.height()` pixels. The glyph's origin is
+    /// at `(shape.left + offset[0], shape.ascent + offset[1])` in the bitmap,
+    /// where the offset is a fraction of a pixel used for subpixel positioning.
+    /// The shape leaves room for the offset.
+    ///
+    /// Glyphs should be drawn in white, so that they can be tinted to any colour
+    /// when the text is drawn.
+    fn rasterize_glyph(
+        &mut self,
+        glyph: GlyphId,
+        font: &Font,
+        shape: &GlyphShape,
+        offset: [f32; 2],
+    ) -> Result<GlyphBitmap>;
+
+    /// Forgets anything cached about a family whose font has changed, for
+    /// instance because a web font for it finished loading.
+    fn invalidate_family(&mut self, _family: &str) {}
+}
+
+/// A glyph produced by shaping text, with its position in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShapedGlyph {
+    pub glyph: GlyphId,
+    /// Index in `Font::families` of the family the glyph comes from.
+    pub face: usize,
+    /// Byte offset in the text of the first character this glyph represents.
+    pub cluster: usize,
+    /// Offset of the glyph's origin from the pen position, with y pointing up.
+    pub x_offset: f32,
+    pub y_offset: f32,
+    /// How far the pen moves after drawing this glyph.
+    pub x_advance: f32,
+}
+
+/// Vertical metrics of a font as a whole, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    /// Distance from the baseline to the top of the font's bounding box.
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the font's bounding box.
+    pub descent: f32,
+    /// Extra space the font recommends between lines.
+    pub line_gap: f32,
+}
+
+impl FontMetrics {
+    /// Distance between the baselines of consecutive lines.
+    pub fn line_height(&self) -> f32 {
+        self.ascent + self.descent + self.line_gap
+    }
+
+    pub(crate) fn scale(&self, scale: f32) -> FontMetrics {
+        FontMetrics {
+            ascent: self.ascent * scale,
+            descent: self.descent * scale,
+            line_gap: self.line_gap * scale,
+        }
+    }
+}
+
+/// An RGBA image of a single glyph, stored row by row with straight (not
+/// premultiplied) alpha.
+pub struct GlyphBitmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// Whether the glyph has colours of its own, like an emoji, and should not be
+    /// tinted with the colour of the text.
+    pub is_color: bool,
+}
+
+impl GlyphBitmap {
+    /// Creates a fully transparent bitmap.
+    pub fn new(width: u32, height: u32) -> GlyphBitmap {
+        GlyphBitmap {
+            width,
+            height,
+            data: vec![0; (width * height * 4) as usize],
+            is_color: false,
+        }
+    }
+
+    /// Whether any reasonably opaque pixel is something other than white, which
+    /// means a glyph that was drawn in white has colours of its own.
+    pub fn has_color(&self) -> bool {
+        // Edge pixels can be off-white after antialiasing.
+        self.data
+            .chunks(4)
+            .any(|pixel| pixel[3] >= 64 && pixel[..3].iter().any(|&channel| channel < 240))
+    }
+
+    /// Multiplies the colour channels by alpha, which is how the atlas stores
+    /// pixels.
+    pub(crate) fn premultiply(&mut self) {
+        for pixel in self.data.chunks_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+    }
+
+    /// Returns a copy of the bitmap with `margin` transparent pixels added on
+    /// every side.
+    pub(crate) fn with_margin(&self, margin: u32) -> GlyphBitmap {
+        let mut result = GlyphBitmap::new(self.width + 2 * margin, self.height + 2 * margin);
+        let row_bytes = (self.width * 4) as usize;
+
+        for row in 0..self.height {
+            let start = (((row + margin) * result.width + margin) * 4) as usize;
+            result.data[start..start + row_bytes]
+                .copy_from_slice(&self.data[row as usize * row_bytes..][..row_bytes]);
+        }
+
+        result
+    }
+}
+
+/// Pixel bounds of a glyph relative to its origin (the point on the baseline
+/// where the pen is when the glyph is drawn), and how far it advances the pen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphShape {
+    /// Extent to the left of the origin.
+    pub left: u32,
+    /// Extent to the right of the origin.
+    pub right: u32,
+    /// Extent above the baseline.
+    pub ascent: u32,
+    /// Extent below the baseline.
+    pub descent: u32,
+    pub occupied_width: f32,
+}
+
+impl GlyphShape {
+    pub fn glyph_width(&self) -> u32 {
+        self.left + self.right
+    }
+
+    pub fn height(&self) -> u32 {
+        self.ascent + self.descent
+    }
+
+    /// Returns the shape grown by `margin` pixels on every side.
+    pub(crate) fn with_margin(&self, margin: u32) -> GlyphShape {
+        GlyphShape {
+            left: self.left + margin,
+            right: self.right + margin,
+            ascent: self.ascent + margin,
+            descent: self.descent + margin,
+            occupied_width: self.occupied_width,
+        }
+    }
+
+    /// Reverses `with_margin`.
+    pub(crate) fn without_margin(&self, margin: u32) -> GlyphShape {
+        GlyphShape {
+            left: self.left.saturating_sub(margin),
+            right: self.right.saturating_sub(margin),
+            ascent: self.ascent.saturating_sub(margin),
+            descent: self.descent.saturating_sub(margin),
+            occupied_width: self.occupied_width,
+        }
+    }
+
+    pub(crate) fn size(&self) -> RectSize {
+        RectSize {
+            width: self.glyph_width(),
+            height: self.height(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(pixels: &[[u8; 4]]) -> GlyphBitmap {
+        GlyphBitmap {
+            width: pixels.len() as u32,
+            height: 1,
+            data: pixels.concat(),
+            is_color: false,
+        }
+    }
+
+    #[test]
+    fn white_glyphs_have_no_color() {
+        assert!(!bitmap(&[[0, 0, 0, 0], [255, 255, 255, 255], [250, 250, 250, 128]]).has_color());
+        // Mostly transparent edge pixels are ignored.
+        assert!(!bitmap(&[[255, 255, 255, 255], [100, 100, 100, 10]]).has_color());
+    }
+
+    #[test]
+    fn colored_pixels_are_detected() {
+        assert!(bitmap(&[[255, 255, 255, 255], [255, 200, 0, 255]]).has_color());
+        assert!(bitmap(&[[0, 0, 0, 255]]).has_color());
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// Shapes every character into its own glyph, with the character code as
+    /// the glyph ID, measures every glyph as the same box and fills it with
+    /// opaque pixels whose red channel is the glyph ID, so that tests can drive
+    /// the atlas without a DOM and check where glyphs ended up.
+    pub struct BoxRasterizer {
+        pub shape: GlyphShape,
+        /// Glyphs missing from the first family of every font, which are taken
+        /// from the second family if there is one.
+        pub missing_glyphs: Vec<GlyphId>,
+        pub metrics: FontMetrics,
+        /// Glyphs reported as colour glyphs.
+        pub color_glyphs: Vec<GlyphId>,
+    }
+
+    impl BoxRasterizer {
+        pub fn new(width: u32, ascent: u32, descent: u32, advance: f32) -> BoxRasterizer {
+            BoxRasterizer {
+                shape: GlyphShape {
+                    left: 0,
+                    right: width,
+                    ascent,
+                    descent,
+                    occupied_width: advance,
+                },
+                metrics: FontMetrics {
+                    ascent: ascent as f32 + 2.,
+                    descent: descent as f32 + 1.,
+                    line_gap: 1.,
+                },
+                color_glyphs: Vec::new(),
+                missing_glyphs: Vec::new(),
+            }
+        }
+    }
+
+    impl GlyphRasterizer for BoxRasterizer {
+        fn shape_text(&mut self, text: &str, font: &Font) -> Result<Vec<ShapedGlyph>> {
+            let has_fallback = font.families().len() > 1;
+
+            Ok(text
+                .char_indices()
+                .map(|(cluster, ch)| ShapedGlyph {
+                    glyph: ch as GlyphId,
+                    face: if has_fallback && self.missing_glyphs.contains(&(ch as GlyphId)) {
+                        1
+                    } else {
+                        0
+                    },
+                    cluster,
+                    x_offset: 0.,
+                    y_offset: 0.,
+                    x_advance: self.shape.occupied_width,
+                })
+                .collect())
+        }
+
+        fn font_metrics(&mut self, _font: &Font) -> Result<FontMetrics> {
+            Ok(self.metrics)
+        }
+
+        fn measure_glyph(&mut self, _glyph: GlyphId, _font: &Font) -> Result<GlyphShape> {
+            Ok(self.shape.clone())
+        }
+
+        fn rasterize_glyph(
+            &mut self,
+            glyph: GlyphId,
+            _font: &Font,
+            shape: &GlyphShape,
+            _offset: [f32; 2],
+        ) -> Result<GlyphBitmap> {
+            let mut bitmap = GlyphBitmap::new(shape.glyph_width(), shape.height());
+            for pixel in bitmap.data.chunks_mut(4) {
+                pixel.copy_from_slice(&[glyph as u8, 0, 0, 255]);
+            }
+            bitmap.is_color = self.color_glyphs.contains(&glyph);
+
+            Ok(bitmap)
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Distance transform based on: Felzenszwalb & Huttenlocher, "Distance Transforms
+// of Sampled Functions" (2012).
+
+use crate::rasterizer::GlyphBitmap;
+
+/// Stand-in for an infinite squared distance which doesn't overflow when added to.
+const FAR: f32 = 1e20;
+
+/// Converts a glyph bitmap into a signed distance field of the same size.
+///
+/// The alpha channel of the result encodes the distance from each pixel to the
+/// glyph's edge, with 0.5 (128) on the edge, higher values inside the glyph and
+/// lower values outside; a distance of `spread` pixels or more saturates. The
+/// colour channels are cleared.
+pub fn signed_distance_field(bitmap: &GlyphBitmap, spread: u32) -> GlyphBitmap {
+    let width = bitmap.width as usize;
+    let height = bitmap.height as usize;
+    let inside: Vec<bool> = bitmap.data.chunks(4).map(|pixel| pixel[3] >= 128).collect();
+
+    // Squared distance from every pixel to the nearest pixel inside the glyph, and
+    // to the nearest pixel outside it.
+    let mut to_inside: Vec<f32> = inside
+        .iter()
+        .map(|&inside| if inside { 0. } else { FAR })
+        .collect();
+    let mut to_outside: Vec<f32> = inside
+        .iter()
+        .map(|&inside| if inside { FAR } else { 0. })
+        .collect();
+    distance_transform_2d(&mut to_inside, width, height);
+    distance_transform_2d(&mut to_outside, width, height);
+
+    let mut result = GlyphBitmap::new(bitmap.width, bitmap.height);
+    let spread = spread.max(1) as f32;
+
+    for (i, pixel) in result.data.chunks_mut(4).enumerate() {
+        // Each distance is measured between pixel centres, so a pixel just inside
+        // and a pixel just outside the edge are half a pixel from it.
+        let distance = if inside[i] {
+            to_outside[i].sqrt() - 0.5
+        } else {
+            0.5 - to_inside[i].sqrt()
+        };
+
+        let value = 0.5 + distance / (2. * spread);
+        pixel[3] = (value.clamp(0., 1.) * 255.).round() as u8;
+    }
+
+    result
+}
+
+/// Replaces each value in a grid of squared distances (0 at features, `FAR`
+/// elsewhere) with the squared euclidean distance to the nearest feature.
+fn distance_transform_2d(grid: &mut [f32], width: usize, height: usize) {
+    let mut buffer = Buffer::new(width.max(height));
+
+    for x in 0..width {
+        buffer.transform((0..height).map(|y| y * width + x), grid);
+    }
+
+    for y in 0..height {
+        buffer.transform((0..width).map(|x| y * width + x), grid);
+    }
+}
+
+/// Scratch space for one-dimensional distance transforms.
+struct Buffer {
+    values: Vec<f32>,
+    /// Locations of the parabolas making up the lower envelope.
+    vertices: Vec<usize>,
+    /// Boundaries between the parabolas of the lower envelope.
+    boundaries: Vec<f32>,
+}
+
+impl Buffer {
+    fn new(size: usize) -> Buffer {
+        Buffer {
+            values: vec![0.; size],
+            vertices: vec![0; size],
+            boundaries: vec![0.; size + 1],
+        }
+    }
+
+    /// Applies the one-dimensional transform to the grid cells at the given indices.
+    fn transform(&mut self, indices: impl Iterator<Item = usize> + Clone, grid: &mut [f32]) {
+        let mut n = 0;
+        for (i, index) in indices.clone().enumerate() {
+            self.values[i] = grid[index];
+            n += 1;
+        }
+        if n == 0 {
+            return;
+        }
+
+        let f = &self.values;
+        let v = &mut self.vertices;
+        let z = &mut self.boundaries;
+
+        let mut k = 0;
+        v[0] = 0;
+        z[0] = -f32::INFINITY;
+        z[1] = f32::INFINITY;
+
+        for q in 1..n {
+            let intersection = |r: usize| {
+                ((f[q] + (q * q) as f32) - (f[r] + (r * r) as f32)) / (2 * q - 2 * r) as f32
+            };
+
+            // Since z[0] is -infinity, this never goes past the first parabola.
+            let mut s = intersection(v[k]);
+            while s <= z[k] {
+                k -= 1;
+                s = intersection(v[k]);
+            }
+
+            k += 1;
+            v[k] = q;
+            z[k] = s;
+            z[k + 1] = f32::INFINITY;
+        }
+
+        k = 0;
+        for (q, index) in indices.enumerate() {
+            while z[k + 1] < q as f32 {
+                k += 1;
+            }
+            let r = v[k];
+            let d = q as f32 - r as f32;
+            grid[index] = d * d + f[r];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A bitmap with the given square filled in.
+    fn square(size: u32, from: u32, to: u32) -> GlyphBitmap {
+        let mut bitmap = GlyphBitmap::new(size, size);
+        for y in from..to {
+            for x in from..to {
+                bitmap.data[((y * size + x) * 4 + 3) as usize] = 255;
+            }
+        }
+
+        bitmap
+    }
+
+    fn alpha_at(bitmap: &GlyphBitmap, x: u32, y: u32) -> u8 {
+        bitmap.data[((y * bitmap.width + x) * 4 + 3) as usize]
+    }
+
+    #[test]
+    fn edge_is_at_half() {
+        let sdf = signed_distance_field(&square(20, 5, 15), 4);
+
+        // Pixels on either side of the edge are half a pixel from it.
+        assert_eq!(143, alpha_at(&sdf, 5, 10));
+        assert_eq!(112, alpha_at(&sdf, 4, 10));
+    }
+
+    #[test]
+    fn distance_increases_inwards() {
+        let sdf = signed_distance_field(&square(20, 5, 15), 4);
+
+        let row: Vec<u8> = (0..10).map(|x| alpha_at(&sdf, x, 10)).collect();
+        assert!(row.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", row);
+    }
+
+    #[test]
+    fn distance_saturates_at_spread() {
+        let sdf = signed_distance_field(&square(30, 10, 20), 2);
+
+        assert_eq!(0, alpha_at(&sdf, 0, 0));
+        assert_eq!(0, alpha_at(&sdf, 10, 5));
+        assert_eq!(255, alpha_at(&sdf, 15, 15));
+    }
+
+    #[test]
+    fn empty_bitmap() {
+        let sdf = signed_distance_field(&GlyphBitmap::new(4, 4), 2);
+
+        assert!(sdf.data.iter().all(|&value| value == 0));
+    }
+}
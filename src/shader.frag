@@ -1,11 +1,28 @@
 #version 300 es
-precision mediump float;
+// Texture coordinates need more than mediump's 11 bits to address every texel
+// of a large atlas page.
+precision highp float;
+precision mediump sampler2DArray;
 
-uniform sampler2D u_texture;
-in vec2 v_tex_coord;
+uniform sampler2DArray u_texture;
+// Whether the atlas holds signed distance fields rather than bitmaps.
+uniform bool u_sdf;
+in vec3 v_tex_coord;
+// Premultiplied colour to tint the glyph with.
+in vec4 v_color;
 out vec4 f_color;
 
 void main() {
-    f_color = texture(u_texture, v_tex_coord);
-    //f_color = vec4(f_color.rgb * f_color.a, f_color.a);
+    vec4 texel = texture(u_texture, v_tex_coord);
+
+    if (u_sdf) {
+        // The edge is where the distance crosses 0.5; smooth it over about a
+        // screen pixel, whatever the scale.
+        float width = fwidth(texel.a);
+        f_color = v_color * smoothstep(0.5 - width, 0.5 + width, texel.a);
+    } else {
+        // Glyphs are white and premultiplied, so this scales the colour by the
+        // glyph's coverage.
+        f_color = v_color * texel;
+    }
 }
@@ -1,11 +1,26 @@
 #version 300 es
 
+// Each instance is a glyph, drawn as a quad spanned by two edges from the
+// corner the upper left of its texture rect is drawn at.
 in vec2 a_position;
-in vec2 a_tex_coord;
+in vec2 a_x_edge;
+in vec2 a_y_edge;
+in vec2 a_tex_position;
+in vec2 a_tex_size;
+in float a_layer;
+in vec4 a_color;
 
-out vec2 v_tex_coord;
+out highp vec3 v_tex_coord;
+out vec4 v_color;
+
+uniform mat4 u_transform;
 
 void main() {
-    gl_Position = vec4(a_position, 0., 1.);
-    v_tex_coord = a_tex_coord;
+    // Corners of a triangle strip: (0, 0), (1, 0), (0, 1), (1, 1).
+    vec2 corner = vec2(gl_VertexID & 1, gl_VertexID >> 1);
+    vec2 position = a_position + corner.x * a_x_edge + corner.y * a_y_edge;
+
+    gl_Position = u_transform * vec4(position, 0., 1.);
+    v_tex_coord = vec3(a_tex_position + corner * a_tex_size, a_layer);
+    v_color = vec4(a_color.rgb * a_color.a, a_color.a);
 }
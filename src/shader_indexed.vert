@@ -0,0 +1,18 @@
+#version 300 es
+
+// Each vertex is a corner of a glyph's quad.
+in vec2 a_position;
+in vec2 a_tex_coord;
+in float a_layer;
+in vec4 a_color;
+
+out highp vec3 v_tex_coord;
+out vec4 v_color;
+
+uniform mat4 u_transform;
+
+void main() {
+    gl_Position = u_transform * vec4(a_position, 0., 1.);
+    v_tex_coord = vec3(a_tex_coord, a_layer);
+    v_color = vec4(a_color.rgb * a_color.a, a_color.a);
+}
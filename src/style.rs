@@ -0,0 +1,123 @@
+use crate::transform::Affine;
+
+/// Horizontal alignment of the lines of a text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+    /// Words are spread out so that every line but the last one of each
+    /// paragraph fills the width of the text. Words within a run of
+    /// right-to-left text are not spread out (see `TextStyle::max_width`).
+    Justify,
+}
+
+/// Which part of the text is placed at the `y` coordinate it is queued at, like
+/// the canvas `textBaseline` property. Ascent and descent are those of the font
+/// rather than of the glyphs, so text is placed consistently whatever it says.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextBaseline {
+    /// The top of the first line.
+    Top,
+    /// Halfway between the top of the first line and the bottom of the last.
+    Middle,
+    /// The baseline of the first line.
+    Alphabetic,
+    /// The bottom of the last line.
+    Bottom,
+}
+
+/// How queued text is laid out and drawn.
+///
+/// Lines are aligned within `max_width`, or within the widest line if there is
+/// no maximum width.
+///
+/// ```ignore
+/// let style = TextStyle::new()
+///     .color([0.8, 0.1, 0.1, 1.])
+///     .max_width(300.)
+///     .align(TextAlign::Center);
+/// renderer.queue_text_with_style("Hello", &font, 10., 20., &style);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextStyle {
+    /// Straight (not premultiplied) RGBA, with components between 0 and 1.
+    pub color: [f32; 4],
+    /// Width in pixels beyond which lines are wrapped at the nearest line break
+    /// opportunity.
+    ///
+    /// Line breaking expects glyphs in the order the text is written, as in
+    /// left-to-right scripts. A run of right-to-left text is shaped in visual
+    /// order, so it is kept whole on one line, even past `max_width` or across
+    /// newlines.
+    pub max_width: Option<f32>,
+    pub align: TextAlign,
+    /// Distance between the baselines of consecutive lines, as a multiple of
+    /// the line height of the font (its ascent, descent and line gap).
+    pub line_height: f32,
+    pub baseline: TextBaseline,
+    /// Transform applied to the laid out text, around `anchor`.
+    pub transform: Option<Affine>,
+    /// Point the transform is applied around, relative to the position the
+    /// text is queued at.
+    pub anchor: [f32; 2],
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            color: [0., 0., 0., 1.],
+            max_width: None,
+            align: TextAlign::Left,
+            line_height: 1.,
+            baseline: TextBaseline::Alphabetic,
+            transform: None,
+            anchor: [0., 0.],
+        }
+    }
+}
+
+impl TextStyle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn max_width(mut self, max_width: f32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn align(mut self, align: TextAlign) -> Self {
+        self.align = align;
+        self
+    }
+
+    pub fn line_height(mut self, line_height: f32) -> Self {
+        self.line_height = line_height;
+        self
+    }
+
+    pub fn baseline(mut self, baseline: TextBaseline) -> Self {
+        self.baseline = baseline;
+        self
+    }
+
+    /// Rotates, scales or skews the text around its anchor (by default, the
+    /// position it is queued at). Glyphs are rasterized at their untransformed
+    /// size and aren't snapped to pixels, so text that is scaled up looks best
+    /// with `GlyphMode::SignedDistanceField`.
+    pub fn transform(mut self, transform: Affine) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+
+    pub fn anchor(mut self, anchor: [f32; 2]) -> Self {
+        self.anchor = anchor;
+        self
+    }
+}
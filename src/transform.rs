@@ -0,0 +1,179 @@
+use crate::options::YAxis;
+
+/// A 4x4 matrix in column-major order, as WebGL expects.
+pub type Matrix = [f32; 16];
+
+pub const IDENTITY: Matrix = [
+    1., 0., 0., 0., //
+    0., 1., 0., 0., //
+    0., 0., 1., 0., //
+    0., 0., 0., 1., //
+];
+
+/// Maps pixel coordinates, with the origin at a corner of a drawing buffer of
+/// the given size, to clip space.
+pub(crate) fn pixel_projection(width: f32, height: f32, y_axis: YAxis) -> Matrix {
+    let (y_scale, y_offset) = match y_axis {
+        YAxis::Up => (2. / height, -1.),
+        YAxis::Down => (-2. / height, 1.),
+    };
+
+    [
+        2. / width,
+        0.,
+        0.,
+        0., //
+        0.,
+        y_scale,
+        0.,
+        0., //
+        0.,
+        0.,
+        1.,
+        0., //
+        -1.,
+        y_offset,
+        0.,
+        1., //
+    ]
+}
+
+/// A 2D affine transform, mapping `(x, y)` to
+/// `(a * x + c * y + e, b * x + d * y + f)` for `[a, b, c, d, e, f]`, like the
+/// arguments of the canvas `setTransform` method.
+///
+/// ```ignore
+/// // Rotate a quarter turn, then double the size.
+/// let transform = Affine::rotate(std::f32::consts::FRAC_PI_2).then(&Affine::scale(2., 2.));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine(pub [f32; 6]);
+
+impl Default for Affine {
+    fn default() -> Self {
+        Affine::identity()
+    }
+}
+
+impl Affine {
+    pub fn identity() -> Affine {
+        Affine([1., 0., 0., 1., 0., 0.])
+    }
+
+    pub fn translate(x: f32, y: f32) -> Affine {
+        Affine([1., 0., 0., 1., x, y])
+    }
+
+    pub fn scale(x: f32, y: f32) -> Affine {
+        Affine([x, 0., 0., y, 0., 0.])
+    }
+
+    /// Rotates by an angle in radians, from the x axis towards the y axis (so
+    /// clockwise when y points down).
+    pub fn rotate(angle: f32) -> Affine {
+        let (sin, cos) = angle.sin_cos();
+        Affine([cos, sin, -sin, cos, 0., 0.])
+    }
+
+    /// Skews by angles in radians: `x` slants vertical lines and `y` slants
+    /// horizontal ones.
+    pub fn skew(x: f32, y: f32) -> Affine {
+        Affine([1., y.tan(), x.tan(), 1., 0., 0.])
+    }
+
+    /// The transform that applies this one and then `next`.
+    pub fn then(&self, next: &Affine) -> Affine {
+        let [a, b, c, d, e, f] = self.0;
+        let [na, nb, nc, nd, ne, nf] = next.0;
+
+        Affine([
+            na * a + nc * b,
+            nb * a + nd * b,
+            na * c + nc * d,
+            nb * c + nd * d,
+            na * e + nc * f + ne,
+            nb * e + nd * f + nf,
+        ])
+    }
+
+    pub fn apply(&self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [a, b, c, d, e, f] = self.0;
+        [a * x + c * y + e, b * x + d * y + f]
+    }
+}
+
+/// The matrix that applies `b` and then `a`.
+pub(crate) fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [0.; 16];
+    for column in 0..4 {
+        for row in 0..4 {
+            result[column * 4 + row] = (0..4).map(|i| a[i * 4 + row] * b[column * 4 + i]).sum();
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+pub(crate) mod testing {
+    /// Asserts that two points are equal up to rounding errors.
+    pub fn assert_close(expected: [f32; 2], actual: [f32; 2]) {
+        assert!(
+            (expected[0] - actual[0]).abs() < 1e-5 && (expected[1] - actual[1]).abs() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::assert_close;
+    use super::*;
+
+    fn apply(matrix: &Matrix, [x, y]: [f32; 2]) -> [f32; 2] {
+        [
+            matrix[0] * x + matrix[4] * y + matrix[12],
+            matrix[1] * x + matrix[5] * y + matrix[13],
+        ]
+    }
+
+    #[test]
+    fn pixels_map_to_clip_space() {
+        let up = pixel_projection(200., 100., YAxis::Up);
+        assert_eq!([-1., -1.], apply(&up, [0., 0.]));
+        assert_eq!([1., 1.], apply(&up, [200., 100.]));
+
+        let down = pixel_projection(200., 100., YAxis::Down);
+        assert_eq!([-1., 1.], apply(&down, [0., 0.]));
+        assert_eq!([0., -1.], apply(&down, [100., 100.]));
+    }
+
+    #[test]
+    fn matrices_are_applied_right_to_left() {
+        let mut translate = IDENTITY;
+        translate[12] = 10.;
+        let mut scale = IDENTITY;
+        scale[0] = 2.;
+
+        assert_eq!([12., 0.], apply(&multiply(&translate, &scale), [1., 0.]));
+        assert_eq!([22., 0.], apply(&multiply(&scale, &translate), [1., 0.]));
+        assert_eq!(translate, multiply(&IDENTITY, &translate));
+    }
+
+    #[test]
+    fn affine_transforms_are_applied_in_order() {
+        let rotate = Affine::rotate(std::f32::consts::FRAC_PI_2);
+        assert_close([0., 1.], rotate.apply([1., 0.]));
+
+        let translate = Affine::translate(10., 0.);
+        assert_close([10., 1.], rotate.then(&translate).apply([1., 0.]));
+        assert_close([0., 11.], translate.then(&rotate).apply([1., 0.]));
+
+        assert_close(
+            [3., 1.],
+            Affine::skew(std::f32::consts::FRAC_PI_4, 0.).apply([2., 1.]),
+        );
+    }
+}